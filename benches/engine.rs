@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main, Throughput};
+
+use poor_man_etl::engine::Engine;
+use poor_man_etl::extractor::{Extractor, ExtractorError};
+use poor_man_etl::loader::{DiscardedOrder, Loader};
+use poor_man_etl::order::Order;
+use poor_man_etl::record::MapRecord;
+use poor_man_etl::reporter::Reporter;
+use poor_man_etl::traderjoes::transformer::TraderJoesTransformer;
+use poor_man_etl::transformer::DiscardedRecord;
+
+const RECORDS: u64 = 100_000;
+
+fn etl(criterion: &mut Criterion) {
+    let transformer = TraderJoesTransformer::new();
+    let reporter = IgnoringReporter {};
+
+    let mut group = criterion.benchmark_group("etl");
+    group.throughput(Throughput::Elements(RECORDS));
+    group.sample_size(10);
+    for workers in [1, 2, 4, 8].iter() {
+        let engine = Engine::builder().with_workers(*workers).build();
+        group.bench_with_input(BenchmarkId::new("workers", workers), workers, |bencher, _| {
+            bencher.iter_batched(
+                || (VecExtractor::of(RECORDS), CountingLoader { count: 0 }),
+                |(mut extractor, mut loader)| {
+                    engine.run(&mut extractor, &transformer, &reporter, &mut loader).unwrap();
+                    loader.count
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, etl);
+criterion_main!(benches);
+
+struct VecExtractor {
+    records: std::vec::IntoIter<MapRecord>,
+}
+
+impl VecExtractor {
+    fn of(count: u64) -> Self {
+        let records: Vec<MapRecord> = (1..=count)
+            .map(|id| MapRecord::new(id, record(id)))
+            .collect();
+        VecExtractor { records: records.into_iter() }
+    }
+}
+
+impl Extractor<MapRecord> for VecExtractor {}
+
+impl Iterator for VecExtractor {
+    type Item = Result<MapRecord, ExtractorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next().map(Ok)
+    }
+}
+
+fn record(id: u64) -> HashMap<String, String> {
+    vec![
+        ("Order Number".to_string(), id.to_string()),
+        ("Year".to_string(), "2019".to_string()),
+        ("Month".to_string(), (id % 12 + 1).to_string()),
+        ("Day".to_string(), (id % 28 + 1).to_string()),
+        ("Product Number".to_string(), format!("P{}", id % 1000)),
+        ("Product Name".to_string(), "Nuts".to_string()),
+        ("Count".to_string(), format!("{}.{}", id % 100 + 1, id % 100)),
+    ].into_iter().collect()
+}
+
+struct IgnoringReporter {}
+
+impl Reporter for IgnoringReporter {
+    fn report_record(&self, _discarded_record: DiscardedRecord) {}
+
+    fn report_order(&self, _discarded_order: DiscardedOrder) {}
+}
+
+struct CountingLoader {
+    count: u64,
+}
+
+impl Loader for CountingLoader {
+    fn load(&mut self, _order: Order) -> Result<(), DiscardedOrder> {
+        self.count += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Id of the last record whose outcome (loaded or discarded) is final, persisted in a local state file so that an
+/// interrupted run can be resumed where it stopped.
+pub struct Checkpoint {
+    path: PathBuf,
+    last_id: Option<u64>,
+}
+
+impl Checkpoint {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Checkpoint> {
+        let path = path.as_ref().to_path_buf();
+        let last_id = match fs::read_to_string(&path) {
+            Ok(content) => Some(content.trim().parse::<u64>()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        Ok(Checkpoint { path, last_id })
+    }
+
+    pub fn last_id(&self) -> Option<u64> {
+        self.last_id
+    }
+
+    /// Replaces the state file atomically, so a crash leaves either the previous or the new id behind.
+    pub fn commit(&mut self, id: u64) -> io::Result<()> {
+        let mut temporary_path = OsString::from(&self.path);
+        temporary_path.push(".tmp");
+        fs::write(&temporary_path, id.to_string())?;
+        fs::rename(&temporary_path, &self.path)?;
+        self.last_id = Some(id);
+        Ok(())
+    }
+
+    /// Removes the state file once the run is complete, so that the next run starts from scratch.
+    pub fn clear(self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn should_start_without_last_id() {
+        let directory = tempdir().unwrap();
+
+        let checkpoint = Checkpoint::open(directory.path().join("checkpoint")).unwrap();
+
+        assert_eq!(checkpoint.last_id(), None);
+    }
+
+    #[test]
+    fn should_resume_from_last_committed_id() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("checkpoint");
+        let mut checkpoint = Checkpoint::open(&path).unwrap();
+        checkpoint.commit(12).unwrap();
+
+        let checkpoint = Checkpoint::open(&path).unwrap();
+
+        assert_eq!(checkpoint.last_id(), Some(12));
+    }
+
+    #[test]
+    fn should_remove_state_file_when_cleared() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("checkpoint");
+        let mut checkpoint = Checkpoint::open(&path).unwrap();
+        checkpoint.commit(12).unwrap();
+
+        checkpoint.clear().unwrap();
+
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn should_reject_corrupted_state_file() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("checkpoint");
+        fs::write(&path, "twelve").unwrap();
+
+        assert_eq!(Checkpoint::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
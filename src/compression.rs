@@ -0,0 +1,194 @@
+use std::io::{self, Chain, Cursor, Read, Write};
+use std::path::Path;
+
+use bzip2::read::MultiBzDecoder;
+use bzip2::write::BzEncoder;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+/// Magic of the first block of a bzip2 stream, or of its end when empty, following the block size digit.
+const BZIP2_BLOCK_MAGICS: [&[u8]; 2] = [&[0x31, 0x41, 0x59, 0x26, 0x53, 0x59], &[0x17, 0x72, 0x45, 0x38, 0x50, 0x90]];
+const MAGIC_SIZE: u64 = 10;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Compression suggested by the extension of a path (`.gz`, `.zst` or `.bz2`).
+    pub fn of(path: &Path) -> Compression {
+        match path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).as_deref() {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            Some("bz2") => Compression::Bzip2,
+            _ => Compression::None,
+        }
+    }
+
+    fn detect(magic: &[u8]) -> Compression {
+        if magic.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if magic.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if magic.starts_with(BZIP2_MAGIC)
+            && magic.get(3).is_some_and(|size| (b'1'..=b'9').contains(size))
+            && BZIP2_BLOCK_MAGICS.iter().any(|block_magic| magic[4..].starts_with(block_magic)) {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+}
+
+type Peeked<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Reader decompressing its input when it starts with the magic bytes of a supported compression, and passing it
+/// through otherwise. Concatenated gzip members and bzip2 streams are read one after the other.
+pub enum Decompressed<R: Read> {
+    None(Peeked<R>),
+    Gzip(MultiGzDecoder<Peeked<R>>),
+    Zstd(zstd::Decoder<'static, io::BufReader<Peeked<R>>>),
+    Bzip2(MultiBzDecoder<Peeked<R>>),
+}
+
+impl<R: Read> Decompressed<R> {
+    /// Reads the first bytes of the input to tell its compression, and then replays them to the decompressor.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = Vec::new();
+        (&mut reader).take(MAGIC_SIZE).read_to_end(&mut magic)?;
+        let compression = Compression::detect(&magic);
+        let reader = Cursor::new(magic).chain(reader);
+        Ok(match compression {
+            Compression::None => Decompressed::None(reader),
+            Compression::Gzip => Decompressed::Gzip(MultiGzDecoder::new(reader)),
+            Compression::Zstd => Decompressed::Zstd(zstd::Decoder::new(reader)?),
+            Compression::Bzip2 => Decompressed::Bzip2(MultiBzDecoder::new(reader)),
+        })
+    }
+}
+
+impl<R: Read> Read for Decompressed<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decompressed::None(reader) => reader.read(buffer),
+            Decompressed::Gzip(reader) => reader.read(buffer),
+            Decompressed::Zstd(reader) => reader.read(buffer),
+            Decompressed::Bzip2(reader) => reader.read(buffer),
+        }
+    }
+}
+
+/// Writer compressing its output. The compressed stream is complete only once `finish` has been called; flushing only
+/// passes on what has already been compressed, so that frequent flushes do not hurt the compression ratio.
+pub enum Compressed<W: Write> {
+    None(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+    Bzip2(BzEncoder<W>),
+}
+
+impl<W: Write> Compressed<W> {
+    pub fn new(writer: W, compression: Compression) -> io::Result<Self> {
+        Ok(match compression {
+            Compression::None => Compressed::None(writer),
+            Compression::Gzip => Compressed::Gzip(GzEncoder::new(writer, flate2::Compression::default())),
+            Compression::Zstd => Compressed::Zstd(zstd::Encoder::new(writer, 0)?),
+            Compression::Bzip2 => Compressed::Bzip2(BzEncoder::new(writer, bzip2::Compression::default())),
+        })
+    }
+
+    /// Writes the end of the compressed stream; nothing should be written afterwards.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self {
+            Compressed::None(writer) => writer.flush(),
+            Compressed::Gzip(writer) => writer.try_finish(),
+            Compressed::Zstd(writer) => writer.do_finish(),
+            Compressed::Bzip2(writer) => writer.try_finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Compressed<W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        match self {
+            Compressed::None(writer) => writer.write(buffer),
+            Compressed::Gzip(writer) => writer.write(buffer),
+            Compressed::Zstd(writer) => writer.write(buffer),
+            Compressed::Bzip2(writer) => writer.write(buffer),
+        }
+    }
+
+    /// Does not make a compressed output durable: what the encoder still buffers is only written once finished.
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Compressed::None(writer) => writer.flush(),
+            Compressed::Gzip(writer) => writer.get_mut().flush(),
+            Compressed::Zstd(writer) => writer.get_mut().flush(),
+            Compressed::Bzip2(writer) => writer.get_mut().flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_tell_compression_from_extension() {
+        assert_eq!(Compression::of(Path::new("orders.csv.gz")), Compression::Gzip);
+        assert_eq!(Compression::of(Path::new("orders.csv.ZST")), Compression::Zstd);
+        assert_eq!(Compression::of(Path::new("orders.ndjson.bz2")), Compression::Bzip2);
+        assert_eq!(Compression::of(Path::new("orders.csv")), Compression::None);
+    }
+
+    #[test]
+    fn should_round_trip() {
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd, Compression::Bzip2] {
+            let compressed = compressed(b"Order Number,Product Name\n13,Nuts\n", compression);
+
+            assert_eq!(decompressed(&compressed), "Order Number,Product Name\n13,Nuts\n", "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn should_read_concatenated_streams() {
+        for compression in [Compression::Gzip, Compression::Zstd, Compression::Bzip2] {
+            let mut concatenated = compressed(b"13,Nuts\n", compression);
+            concatenated.extend(compressed(b"16,Jam\n", compression));
+
+            assert_eq!(decompressed(&concatenated), "13,Nuts\n16,Jam\n", "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn should_pass_through_text_resembling_magic_bytes() {
+        assert_eq!(decompressed(b"BZh,Name\n1,Nuts\n"), "BZh,Name\n1,Nuts\n");
+        assert_eq!(decompressed(b"BZ"), "BZ");
+        assert_eq!(decompressed(b""), "");
+    }
+
+    fn compressed(content: &[u8], compression: Compression) -> Vec<u8> {
+        let mut writer = Compressed::new(vec![], compression).unwrap();
+        writer.write_all(content).unwrap();
+        writer.finish().unwrap();
+        match writer {
+            Compressed::None(writer) => writer,
+            Compressed::Gzip(writer) => writer.finish().unwrap(),
+            Compressed::Zstd(writer) => writer.finish().unwrap(),
+            Compressed::Bzip2(writer) => writer.finish().unwrap(),
+        }
+    }
+
+    fn decompressed(content: &[u8]) -> String {
+        let mut decompressed = String::new();
+        Decompressed::new(content).unwrap().read_to_string(&mut decompressed).unwrap();
+        decompressed
+    }
+}
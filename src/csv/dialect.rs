@@ -0,0 +1,189 @@
+use csv::{ReaderBuilder, Trim, WriterBuilder};
+use rust_decimal::Decimal;
+
+use crate::encoding::Encoding;
+
+/// How CSV files are laid out; the default is RFC 4180: UTF-8, comma-delimited, double-quoted, with a header row.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Dialect {
+    delimiter: u8,
+    quote: u8,
+    escape: Option<u8>,
+    comment: Option<u8>,
+    has_headers: bool,
+    column_names: Option<Vec<String>>,
+    trim: bool,
+    flexible: bool,
+    decimal_separator: u8,
+    encoding: Encoding,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect {
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            comment: None,
+            has_headers: true,
+            column_names: None,
+            trim: false,
+            flexible: false,
+            decimal_separator: b'.',
+            encoding: Encoding::default(),
+        }
+    }
+}
+
+impl Dialect {
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn with_quote(mut self, quote: u8) -> Self {
+        self.quote = quote;
+        self
+    }
+
+    /// Escapes quotes inside quoted values with the given character instead of doubling them.
+    pub fn with_escape(mut self, escape: u8) -> Self {
+        self.escape = Some(escape);
+        self
+    }
+
+    /// Skips the lines starting with the given character when reading.
+    pub fn with_comment(mut self, comment: u8) -> Self {
+        self.comment = Some(comment);
+        self
+    }
+
+    /// Whether the first row holds the column names; without one, the extractor needs column names to be given.
+    pub fn with_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+        self
+    }
+
+    /// Names the extracted columns in order, taking precedence over the header row if there is one.
+    pub fn with_column_names(mut self, column_names: Vec<String>) -> Self {
+        self.column_names = Some(column_names);
+        self
+    }
+
+    /// Trims the whitespace around the values and the column names when reading.
+    pub fn with_trimming(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Accepts rows with a varying number of values; the missing ones of a row are then absent from its record.
+    pub fn with_flexible_rows(mut self, flexible: bool) -> Self {
+        self.flexible = flexible;
+        self
+    }
+
+    /// Reads and writes decimals with the given separator instead of a dot (e.g. `12,20`): the extracted values which
+    /// are decimals with this separator get a dot instead, so that they coerce to decimals, while their raw text is
+    /// kept as is.
+    pub fn with_decimal_separator(mut self, decimal_separator: u8) -> Self {
+        self.decimal_separator = decimal_separator;
+        self
+    }
+
+    /// Transcodes the file from the given encoding when reading; files are always written in UTF-8.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn comment(&self) -> Option<u8> {
+        self.comment
+    }
+
+    pub fn has_headers(&self) -> bool {
+        self.has_headers
+    }
+
+    pub fn column_names(&self) -> Option<&[String]> {
+        self.column_names.as_deref()
+    }
+
+    pub fn decimal_separator(&self) -> u8 {
+        self.decimal_separator
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub(crate) fn reader(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder.delimiter(self.delimiter)
+            .quote(self.quote)
+            .escape(self.escape)
+            .double_quote(self.escape.is_none())
+            .comment(self.comment)
+            .has_headers(self.has_headers)
+            .trim(if self.trim { Trim::All } else { Trim::None })
+            .flexible(self.flexible);
+        builder
+    }
+
+    pub(crate) fn writer(&self) -> WriterBuilder {
+        let mut builder = WriterBuilder::new();
+        builder.delimiter(self.delimiter)
+            .quote(self.quote)
+            .double_quote(self.escape.is_none())
+            .escape(self.escape.unwrap_or(b'\\'))
+            .flexible(self.flexible);
+        builder
+    }
+}
+
+/// Value read in a dialect, with a dot in place of the decimal separator when the value is a decimal (e.g. `-12,20`).
+pub(crate) fn read_decimal(value: &str, decimal_separator: u8) -> String {
+    let separator = decimal_separator as char;
+    let number = value.trim();
+    let number = number.strip_prefix(['-', '+']).unwrap_or(number);
+    let is_decimal = separator != '.' && number.split_once(separator).is_some_and(|(integer, fraction)| {
+        !integer.is_empty() && !fraction.is_empty()
+            && integer.bytes().all(|digit| digit.is_ascii_digit())
+            && fraction.bytes().all(|digit| digit.is_ascii_digit())
+    });
+    if is_decimal {
+        value.replacen(separator, ".", 1)
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Text of a decimal written in a dialect.
+pub(crate) fn write_decimal(value: &Decimal, decimal_separator: u8) -> String {
+    let value = value.to_string();
+    match decimal_separator {
+        b'.' => value,
+        separator => value.replacen('.', &(separator as char).to_string(), 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_decimals_with_separator() {
+        assert_eq!(read_decimal("12,20", b','), "12.20");
+        assert_eq!(read_decimal(" -12,20", b','), " -12.20");
+        assert_eq!(read_decimal("12", b','), "12");
+        assert_eq!(read_decimal("Nuts, salted", b','), "Nuts, salted");
+        assert_eq!(read_decimal("1,234,5", b','), "1,234,5");
+        assert_eq!(read_decimal("12,20", b'.'), "12,20");
+    }
+
+    #[test]
+    fn should_write_decimals_with_separator() {
+        assert_eq!(write_decimal(&Decimal::new(-1220, 2), b','), "-12,20");
+        assert_eq!(write_decimal(&Decimal::new(12, 0), b','), "12");
+        assert_eq!(write_decimal(&Decimal::new(1220, 2), b'.'), "12.20");
+    }
+}
@@ -1,54 +1,145 @@
-use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
+use std::io::{self, Read, Stdin};
+use std::path::Path;
 
-use csv::Reader;
+use csv::{ErrorKind, Reader};
 
+use crate::compression::Decompressed;
+use crate::csv::dialect::{self, Dialect};
+use crate::encoding::{self, Decoded};
 use crate::extractor::{Extractor, ExtractorError};
-use crate::record::MapRecord;
+use crate::record::{MapRecord, Provenance};
 
-pub struct CsvExtractor {
-    reader: Reader<File>,
-    headers: HashMap<usize, String>,
+/// Extracts the rows of a CSV input as records, each with its line, byte offset and raw text as provenance.
+pub struct CsvExtractor<R: Read = File> {
+    reader: Reader<Recording<Decoded<Decompressed<R>>>>,
+    headers: Vec<String>,
+    comment: Option<u8>,
+    decimal_separator: u8,
     position: u64,
 }
 
-impl CsvExtractor {
-    pub fn from(file: File) -> Result<Self, Box<dyn Error>> {
-        let mut reader = Reader::from_reader(file);
+impl CsvExtractor<File> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        CsvExtractor::from(File::open(path)?)
+    }
+}
+
+impl CsvExtractor<Stdin> {
+    pub fn stdin() -> Result<Self, Box<dyn Error>> {
+        CsvExtractor::from(io::stdin())
+    }
+}
+
+impl<R: Read> CsvExtractor<R> {
+    pub fn from(reader: R) -> Result<Self, Box<dyn Error>> {
+        CsvExtractor::with_dialect(reader, &Dialect::default())
+    }
+
+    /// Extracts the rows of an input in the given dialect, decompressing it when compressed; the values of a row
+    /// beyond the named columns are ignored.
+    pub fn with_dialect(reader: R, dialect: &Dialect) -> Result<Self, Box<dyn Error>> {
+        let decoded = encoding::decode(Decompressed::new(reader)?, dialect.encoding())?;
+        let mut reader = dialect.reader().from_reader(Recording { reader: decoded, recorded: Vec::new(), offset: 0 });
 
-        let headers = reader.headers();
-        let headers = match headers.iter().next() {
-            Some(headers) => headers,
-            None => return Err(Box::new(ExtractorError::new("missing headers"))),
+        let headers = match dialect.column_names() {
+            Some(column_names) => column_names.to_vec(),
+            None if dialect.has_headers() => match reader.headers() {
+                Ok(headers) => headers.iter().map(str::to_owned).collect(),
+                Err(_) => return Err(Box::new(ExtractorError::new("missing headers"))),
+            },
+            None => return Err(Box::new(ExtractorError::new("missing column names"))),
         };
-        let headers = headers.iter().enumerate()
-            .map(|(index, name)| (index, name.to_owned()))
-            .collect();
 
-        Ok(CsvExtractor { reader, headers, position: 0 })
+        Ok(CsvExtractor { reader, headers, comment: dialect.comment(), decimal_separator: dialect.decimal_separator(), position: 0 })
     }
 }
 
-impl Extractor<MapRecord> for CsvExtractor {}
+impl<R: Read> Extractor<MapRecord> for CsvExtractor<R> {}
 
-impl Iterator for CsvExtractor {
-    type Item = MapRecord;
+impl<R: Read> Iterator for CsvExtractor<R> {
+    type Item = Result<MapRecord, ExtractorError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.reader.records().next()
-            .map(|record| {
-                self.position = self.position + 1;
-                match record {
-                    Ok(record) => {
-                        let values = record.iter().enumerate()
-                            .map(|(value_number, value)| (self.headers.get(&value_number).unwrap().to_owned(), value.to_owned()))
-                            .collect();
-                        MapRecord::new(self.position, values)
-                    }
-                    Err(_) => MapRecord::new(self.position, HashMap::new())
-                }
-            })
+        let record = self.reader.records().next()?;
+        self.position += 1;
+        let start = match &record {
+            Ok(record) => record.position(),
+            Err(e) => e.position(),
+        }.map(|position| (position.line(), position.byte()));
+        let provenance = match start {
+            Some((line, byte)) => {
+                let end = self.reader.position().byte();
+                let raw_text = self.reader.get_mut().take(byte, end);
+                let (line, byte, raw_text) = skip_ignored_lines(line, byte, &raw_text, self.comment);
+                Provenance::default().with_line(line).with_byte(byte).with_raw_text(raw_text.trim_end_matches(['\r', '\n']))
+            }
+            None => Provenance::default(),
+        };
+        Some(match record {
+            Ok(record) => {
+                let values = self.headers.iter().zip(record.iter())
+                    .map(|(name, value)| (name.to_owned(), dialect::read_decimal(value, self.decimal_separator)))
+                    .collect();
+                Ok(MapRecord::with_columns(self.position, values).with_provenance(provenance))
+            }
+            Err(e) => {
+                let category = category_of(&e);
+                Err(ExtractorError::caused_by(self.position, e).with_category(category).with_provenance(provenance))
+            }
+        })
+    }
+}
+
+fn category_of(error: &csv::Error) -> &'static str {
+    match error.kind() {
+        ErrorKind::Io(_) => "I/O error",
+        ErrorKind::Utf8 { .. } => "invalid UTF-8",
+        ErrorKind::UnequalLengths { .. } => "wrong number of fields",
+        _ => "malformed row",
+    }
+}
+
+/// Skips the blank and comment lines the reader went past before a row, which it counts as the start of the row.
+fn skip_ignored_lines(mut line: u64, mut byte: u64, mut raw_text: &str, comment: Option<u8>) -> (u64, u64, &str) {
+    while let Some(end) = raw_text.find('\n') {
+        let first_line = raw_text[..end].trim_end_matches('\r');
+        if !first_line.is_empty() && first_line.as_bytes().first() != comment.as_ref() {
+            break;
+        }
+        line += 1;
+        byte += end as u64 + 1;
+        raw_text = &raw_text[end + 1..];
+    }
+    (line, byte, raw_text)
+}
+
+/// Reader keeping what it reads until taken, so that the raw text of a row can be recovered once the row is parsed.
+struct Recording<R: Read> {
+    reader: R,
+    recorded: Vec<u8>,
+    /// Offset in the input of the first recorded byte.
+    offset: u64,
+}
+
+impl<R: Read> Recording<R> {
+    /// Text between the given offsets of the input, forgetting everything recorded before the end.
+    fn take(&mut self, start: u64, end: u64) -> String {
+        let start = (start.saturating_sub(self.offset) as usize).min(self.recorded.len());
+        let end = (end.saturating_sub(self.offset) as usize).clamp(start, self.recorded.len());
+        let text = String::from_utf8_lossy(&self.recorded[start..end]).into_owned();
+        self.recorded.drain(..end);
+        self.offset += end as u64;
+        text
+    }
+}
+
+impl<R: Read> Read for Recording<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buffer)?;
+        self.recorded.extend_from_slice(&buffer[..read]);
+        Ok(read)
     }
 }
 
@@ -56,7 +147,10 @@ impl Iterator for CsvExtractor {
 mod tests {
     use std::io::{Seek, SeekFrom, Write};
 
-    use tempfile::tempfile;
+    use flate2::write::GzEncoder;
+    use tempfile::{tempdir, tempfile};
+
+    use crate::record::Record;
 
     use super::*;
 
@@ -68,20 +162,164 @@ mod tests {
                       Value 2,Another Value 2").unwrap();
         file.seek(SeekFrom::Start(0)).unwrap();
 
-        let mut extracted_records: Vec<MapRecord> = CsvExtractor::from(file).unwrap().into_iter()
+        let mut extracted_records: Vec<MapRecord> = CsvExtractor::from(file).unwrap()
+            .map(Result::unwrap)
             .collect();
 
-        let first_expected_record = MapRecord::new(1, vec![
+        let first_expected_record = MapRecord::with_columns(1, vec![
             ("Column".to_string(), "Value 1".to_string()),
             ("Another Column".to_string(), "Another Value 1".to_string()),
-        ].into_iter().collect());
-        let second_expected_record = MapRecord::new(2, vec![
+        ]).with_provenance(at(2, 22, "Value 1,Another Value 1"));
+        let second_expected_record = MapRecord::with_columns(2, vec![
             ("Column".to_string(), "Value 2".to_string()),
             ("Another Column".to_string(), "Another Value 2".to_string()),
-        ].into_iter().collect());
+        ]).with_provenance(at(3, 46, "Value 2,Another Value 2"));
 
         assert_eq!(extracted_records.len(), 2);
         assert_eq!(extracted_records.remove(0), first_expected_record);
         assert_eq!(extracted_records.remove(0), second_expected_record);
     }
+
+    #[test]
+    fn should_extract_from_memory() {
+        let extracted_records: Vec<MapRecord> = CsvExtractor::from("Column\nValue 1".as_bytes()).unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(extracted_records, vec![MapRecord::with_columns(1, vec![("Column".to_string(), "Value 1".to_string())]).with_provenance(at(2, 7, "Value 1"))]);
+    }
+
+    #[test]
+    fn should_extract_from_path() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("source.csv");
+        std::fs::write(&path, "Column\nValue 1").unwrap();
+
+        let extracted_records: Vec<MapRecord> = CsvExtractor::open(&path).unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(extracted_records.len(), 1);
+    }
+
+    #[test]
+    fn should_extract_in_dialect() {
+        let mut file = tempfile().unwrap();
+        write!(file, "# exported on 2019-08-27\n\
+                       Value 1 ;'Another; Value 1'\n\
+                      Value 2;'Another \\'Value\\' 2';Extra Value").unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let dialect = Dialect::default()
+            .with_delimiter(b';')
+            .with_quote(b'\'')
+            .with_escape(b'\\')
+            .with_comment(b'#')
+            .with_headers(false)
+            .with_column_names(vec!["Column".to_string(), "Another Column".to_string()])
+            .with_trimming(true)
+            .with_flexible_rows(true);
+
+        let extracted_records: Vec<MapRecord> = CsvExtractor::with_dialect(file, &dialect).unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(extracted_records, vec![
+            MapRecord::with_columns(1, vec![
+                ("Column".to_string(), "Value 1".to_string()),
+                ("Another Column".to_string(), "Another; Value 1".to_string()),
+            ]).with_provenance(at(2, 25, "Value 1 ;'Another; Value 1'")),
+            MapRecord::with_columns(2, vec![
+                ("Column".to_string(), "Value 2".to_string()),
+                ("Another Column".to_string(), "Another 'Value' 2".to_string()),
+            ]).with_provenance(at(3, 53, "Value 2;'Another \\'Value\\' 2';Extra Value")),
+        ]);
+    }
+
+    #[test]
+    fn should_extract_decimals_with_separator() {
+        let mut file = tempfile().unwrap();
+        write!(file, "Product Name;Count\nNuts, salted;12,20\n").unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let dialect = Dialect::default().with_delimiter(b';').with_decimal_separator(b',');
+
+        let extracted_records: Vec<MapRecord> = CsvExtractor::with_dialect(file, &dialect).unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(extracted_records, vec![MapRecord::with_columns(1, vec![
+            ("Product Name".to_string(), "Nuts, salted".to_string()),
+            ("Count".to_string(), "12.20".to_string()),
+        ]).with_provenance(at(2, 19, "Nuts, salted;12,20"))]);
+    }
+
+    #[test]
+    fn should_extract_in_encoding() {
+        let mut file = tempfile().unwrap();
+        file.write_all(b"Product Name\nCr\xe8me br\xfbl\xe9e").unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+
+        let extracted_records: Vec<MapRecord> = CsvExtractor::with_dialect(file, &Dialect::default().with_encoding("windows-1252".parse().unwrap())).unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(extracted_records, vec![MapRecord::with_columns(1, vec![("Product Name".to_string(), "Crème brûlée".to_string())]).with_provenance(at(2, 13, "Crème brûlée"))]);
+    }
+
+    #[test]
+    fn should_extract_compressed() {
+        let mut encoder = GzEncoder::new(tempfile().unwrap(), flate2::Compression::default());
+        write!(encoder, "Column\nValue 1").unwrap();
+        let mut file = encoder.finish().unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+
+        let extracted_records: Vec<MapRecord> = CsvExtractor::from(file).unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(extracted_records, vec![MapRecord::with_columns(1, vec![("Column".to_string(), "Value 1".to_string())]).with_provenance(at(2, 7, "Value 1"))]);
+    }
+
+    #[test]
+    fn should_keep_raw_text_of_rows() {
+        let source = "Column,Another Column\r\n\r\n\"Value\r\n1\",Another Value 1\r\nValue 2,Another Value 2";
+
+        let provenances: Vec<Provenance> = CsvExtractor::from(source.as_bytes()).unwrap()
+            .map(|record| record.unwrap().provenance().clone())
+            .collect();
+
+        assert_eq!(provenances, vec![at(3, 25, "\"Value\r\n1\",Another Value 1"), at(5, 53, "Value 2,Another Value 2")]);
+    }
+
+    #[test]
+    fn should_fail_without_headers_and_column_names() {
+        let file = tempfile().unwrap();
+
+        let error = CsvExtractor::with_dialect(file, &Dialect::default().with_headers(false)).err().unwrap();
+
+        assert_eq!(error.to_string(), "missing column names");
+    }
+
+    #[test]
+    fn should_fail_on_malformed_record() {
+        let mut file = tempfile().unwrap();
+        write!(file, "Column,Another Column\n\
+                      Value 1\n\
+                      Value 2,Another Value 2").unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+
+        let mut extracted_records: Vec<Result<MapRecord, ExtractorError>> = CsvExtractor::from(file).unwrap().collect();
+
+        let error = extracted_records.remove(0).err().unwrap();
+        assert_eq!(error.position(), 1);
+        assert_eq!(error.line(), Some(2));
+        assert_eq!(error.byte(), Some(22));
+        assert_eq!(error.provenance().raw_text(), Some("Value 1"));
+        assert!(error.source().is_some());
+        assert_eq!(error.category(), "wrong number of fields");
+        assert_eq!(extracted_records.remove(0).unwrap().id(), 2);
+    }
+
+    fn at(line: u64, byte: u64, raw_text: &str) -> Provenance {
+        Provenance::default().with_line(line).with_byte(byte).with_raw_text(raw_text)
+    }
 }
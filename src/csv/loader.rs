@@ -1,39 +1,152 @@
 use std::error::Error;
 use std::fs::File;
+use std::io::{self, Stdout, Write};
+use std::path::Path;
 
-use csv::Writer;
+use csv::WriterBuilder;
 
-use crate::loader::{DiscardedOrder, Loader};
-use crate::order::Order;
+use crate::compression::{Compressed, Compression};
+use crate::csv::dialect::{self, Dialect};
+use crate::loader::{DiscardedOrder, Loader, OrderErrorKind};
+use crate::order::{LineItem, Order};
+use crate::staging::Staging;
 
-const HEADERS: [&str; 6] = ["Order Id", "Date Time", "Product Id", "Product Name", "Quantity", "Unit"];
+pub(super) const HEADERS: [&str; 9] = ["Order Id", "Date Time", "Product Id", "Product Name", "Quantity", "Unit", "Unit Price", "Currency", "Total"];
 
-pub struct CsvLoader {
-    writer: Writer<File>,
+pub struct CsvLoader<W: Write = File> {
+    /// Taken out once finished, to complete the compressed stream.
+    writer: Option<Compressed<W>>,
+    /// Serialises the rows of each order in a buffer, written in one go so that a failing order leaves no rows.
+    rows: WriterBuilder,
+    decimal_separator: u8,
+    staging: Option<Staging>,
 }
 
-impl CsvLoader {
-    pub fn to(file: File) -> Result<Self, Box<dyn Error>> {
-        let mut writer = Writer::from_writer(file);
-        writer.write_record(&HEADERS)?;
-        Ok(CsvLoader { writer })
+impl CsvLoader<File> {
+    /// Creates the file at the given path once finished, replacing any existing one; an aborted run leaves the path
+    /// untouched.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let (file, staging) = Staging::create(path)?;
+        Ok(CsvLoader::to(file)?.staged(staging))
     }
 }
 
-impl Loader for CsvLoader {
+impl CsvLoader<Stdout> {
+    pub fn stdout() -> Result<Self, Box<dyn Error>> {
+        CsvLoader::to(io::stdout())
+    }
+}
+
+impl<W: Write> CsvLoader<W> {
+    pub fn to(writer: W) -> Result<Self, Box<dyn Error>> {
+        CsvLoader::with_dialect(writer, &Dialect::default())
+    }
+
+    /// Writes orders in the given dialect, with a header row unless the dialect has none.
+    pub fn with_dialect(writer: W, dialect: &Dialect) -> Result<Self, Box<dyn Error>> {
+        CsvLoader::with_compression(writer, dialect, Compression::None)
+    }
+
+    /// Writes orders in the given dialect and compression; a compressed output is complete only once the loader is
+    /// finished.
+    pub fn with_compression(writer: W, dialect: &Dialect, compression: Compression) -> Result<Self, Box<dyn Error>> {
+        let mut writer = Compressed::new(writer, compression)?;
+        let rows = dialect.writer();
+        if dialect.has_headers() {
+            writer.write_all(&serialise(&rows, [HEADERS])?)?;
+        }
+        Ok(CsvLoader { writer: Some(writer), rows, decimal_separator: dialect.decimal_separator(), staging: None })
+    }
+
+    /// Writes to the temporary file of the given staging, committed once finished and discarded when aborted.
+    pub fn staged(mut self, staging: Staging) -> Self {
+        self.staging = Some(staging);
+        self
+    }
+
+    /// Continues an output written by an interrupted run, without repeating the headers.
+    pub fn append(writer: W) -> Result<Self, Box<dyn Error>> {
+        CsvLoader::append_with_dialect(writer, &Dialect::default())
+    }
+
+    /// Continues an uncompressed output written in the given dialect by an interrupted run, without repeating the
+    /// headers.
+    pub fn append_with_dialect(writer: W, dialect: &Dialect) -> Result<Self, Box<dyn Error>> {
+        Ok(CsvLoader {
+            writer: Some(Compressed::new(writer, Compression::None)?),
+            rows: dialect.writer(),
+            decimal_separator: dialect.decimal_separator(),
+            staging: None,
+        })
+    }
+}
+
+impl<W: Write> Loader for CsvLoader<W> {
+    /// Writes a row per line item, repeating the order id and date; the price columns are left empty for line items
+    /// without a price.
     fn load(&mut self, order: Order) -> Result<(), DiscardedOrder> {
-        if let Err(e) = self.writer.write_record(vec!(
-            order.id().to_string(),
-            order.date().to_string(),
-            order.product_id().to_owned(),
-            order.product_name().to_owned(),
-            order.quantity().quantity().to_string(),
-            format!("{:?}", order.quantity().unit()))
-        ) {
-            return Err(DiscardedOrder::new(order, e.description().to_string()));
+        let writer = self.writer.as_mut().expect("loader already finished");
+        let decimal_separator = self.decimal_separator;
+        let serialised = match serialise(&self.rows, order.line_items().iter().map(|line_item| row(&order, line_item, decimal_separator))) {
+            Ok(serialised) => serialised,
+            Err(e) => return Err(DiscardedOrder::new(order, OrderErrorKind::Encoding, e.to_string())),
+        };
+        writer.write_all(&serialised)
+            .and_then(|()| writer.flush())
+            .map_err(|e| DiscardedOrder::new(order, OrderErrorKind::Io, e.to_string()))
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.finish()?;
+        }
+        if let Some(staging) = self.staging.take() {
+            staging.commit()?;
         }
-        self.writer.flush().or_else(|e| Err(DiscardedOrder::new(order, e.description().to_string())))
+        Ok(())
     }
+
+    /// Removes a staged output; otherwise leaves the rows written so far, without completing a compressed output.
+    fn abort(&mut self) {
+        self.writer = None;
+        if let Some(staging) = self.staging.take() {
+            staging.discard();
+        }
+    }
+}
+
+fn serialise<I, R, F>(rows: &WriterBuilder, records: I) -> csv::Result<Vec<u8>>
+    where I: IntoIterator<Item=R>,
+          R: IntoIterator<Item=F>,
+          F: AsRef<[u8]> {
+    let mut writer = rows.from_writer(Vec::new());
+    for record in records {
+        writer.write_record(record)?;
+    }
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+/// Values of a line item in the order of `HEADERS`, with decimals written with the given separator.
+pub(super) fn row(order: &Order, line_item: &LineItem, decimal_separator: u8) -> Vec<String> {
+    let (unit_price, currency, total) = match line_item.price() {
+        Some(price) => (
+            dialect::write_decimal(price.unit_price(), decimal_separator),
+            price.currency().code().to_owned(),
+            dialect::write_decimal(price.total(), decimal_separator),
+        ),
+        None => Default::default(),
+    };
+    vec!(
+        order.id().to_string(),
+        order.date().to_string(),
+        line_item.product_id().to_owned(),
+        line_item.product_name().to_owned(),
+        dialect::write_decimal(line_item.quantity().quantity(), decimal_separator),
+        format!("{:?}", line_item.quantity().unit()),
+        unit_price,
+        currency,
+        total,
+    )
 }
 
 #[cfg(test)]
@@ -43,9 +156,9 @@ mod tests {
     use chrono::NaiveDate;
     use rust_decimal::Decimal;
 
-    use tempfile::tempfile;
+    use tempfile::{tempdir, tempfile};
 
-    use crate::order::Quantity;
+    use crate::order::{Quantity, Unit};
 
     use super::*;
 
@@ -53,7 +166,7 @@ mod tests {
     fn should_extract() {
         let order = Order::builder()
             .with_id(12)
-            .with_date(NaiveDate::from_ymd(2019, 8, 27))
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
             .with_product_id("123456789".to_string())
             .with_product_name("Nuts".to_string())
             .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
@@ -67,7 +180,219 @@ mod tests {
         let mut loaded_content = String::new();
         cloned.seek(SeekFrom::Start(0)).unwrap();
         cloned.read_to_string(&mut loaded_content).unwrap();
-        assert_eq!(loaded_content, "Order Id,Date Time,Product Id,Product Name,Quantity,Unit\n\
-                    12,2019-08-27,123456789,Nuts,12.20,KG\n");
+        assert_eq!(loaded_content, "Order Id,Date Time,Product Id,Product Name,Quantity,Unit,Unit Price,Currency,Total\n\
+                    12,2019-08-27,123456789,Nuts,12.20,KG,,,\n");
+    }
+
+    #[test]
+    fn should_create_file_only_once_finished() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("orders.csv");
+        let mut loader = CsvLoader::create(&path).unwrap();
+        loader.load(order(12)).unwrap();
+
+        assert!(!path.exists());
+        loader.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn should_leave_no_file_when_aborted() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("orders.csv");
+        let mut loader = CsvLoader::create(&path).unwrap();
+        loader.load(order(12)).unwrap();
+
+        loader.abort();
+
+        assert_eq!(std::fs::read_dir(directory.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn should_append_without_headers() {
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name("Nuts".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
+            .build();
+
+        let file = tempfile().unwrap();
+        let mut cloned = file.try_clone().unwrap();
+        let mut loader = CsvLoader::append(file).unwrap();
+        loader.load(order).unwrap();
+
+        let mut loaded_content = String::new();
+        cloned.seek(SeekFrom::Start(0)).unwrap();
+        cloned.read_to_string(&mut loaded_content).unwrap();
+        assert_eq!(loaded_content, "12,2019-08-27,123456789,Nuts,12.20,KG,,,\n");
+    }
+
+    #[test]
+    fn should_load_in_dialect() {
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name("Nuts; \"salted\"".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
+            .build();
+        let dialect = Dialect::default()
+            .with_delimiter(b';')
+            .with_escape(b'\\')
+            .with_headers(false)
+            .with_decimal_separator(b',');
+
+        let file = tempfile().unwrap();
+        let mut cloned = file.try_clone().unwrap();
+        let mut loader = CsvLoader::with_dialect(file, &dialect).unwrap();
+        loader.load(order).unwrap();
+
+        let mut loaded_content = String::new();
+        cloned.seek(SeekFrom::Start(0)).unwrap();
+        cloned.read_to_string(&mut loaded_content).unwrap();
+        assert_eq!(loaded_content, "12;2019-08-27;123456789;\"Nuts; \\\"salted\\\"\";12,20;KG;;;\n");
+    }
+
+    #[test]
+    fn should_load_to_memory() {
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name("Nuts".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
+            .build();
+
+        let mut loader = CsvLoader::append(vec![]).unwrap();
+        loader.load(order).unwrap();
+
+        match loader.writer.unwrap() {
+            Compressed::None(written) => assert_eq!(String::from_utf8(written).unwrap(), "12,2019-08-27,123456789,Nuts,12.20,KG,,,\n"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_load_compressed() {
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name("Nuts".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
+            .build();
+
+        let file = tempfile().unwrap();
+        let mut cloned = file.try_clone().unwrap();
+        let mut loader = CsvLoader::with_compression(file, &Dialect::default(), Compression::Zstd).unwrap();
+        loader.load(order).unwrap();
+        loader.finish().unwrap();
+
+        let mut loaded_content = String::new();
+        cloned.seek(SeekFrom::Start(0)).unwrap();
+        zstd::Decoder::new(cloned).unwrap().read_to_string(&mut loaded_content).unwrap();
+        assert_eq!(loaded_content, "Order Id,Date Time,Product Id,Product Name,Quantity,Unit,Unit Price,Currency,Total\n\
+                    12,2019-08-27,123456789,Nuts,12.20,KG,,,\n");
+    }
+
+    #[test]
+    fn should_load_price() {
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name("Nuts".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
+            .with_unit_price(Decimal::new(250, 2))
+            .with_currency("USD".parse().unwrap())
+            .build();
+
+        let file = tempfile().unwrap();
+        let mut cloned = file.try_clone().unwrap();
+        let mut loader = CsvLoader::append(file).unwrap();
+        loader.load(order).unwrap();
+
+        let mut loaded_content = String::new();
+        cloned.seek(SeekFrom::Start(0)).unwrap();
+        cloned.read_to_string(&mut loaded_content).unwrap();
+        assert_eq!(loaded_content, "12,2019-08-27,123456789,Nuts,12.20,KG,2.50,USD,30.50\n");
+    }
+
+    #[test]
+    fn should_load_a_row_per_line_item() {
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name("Nuts".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
+            .with_line_item(LineItem::builder()
+                .with_product_id("987654321".to_string())
+                .with_product_name("Jam".to_string())
+                .with_quantity(Quantity::builder().with_quantity(Decimal::new(1, 0)).with_unit(Unit::EACH).build())
+                .build())
+            .build();
+
+        let file = tempfile().unwrap();
+        let mut cloned = file.try_clone().unwrap();
+        let mut loader = CsvLoader::append(file).unwrap();
+        loader.load(order).unwrap();
+
+        let mut loaded_content = String::new();
+        cloned.seek(SeekFrom::Start(0)).unwrap();
+        cloned.read_to_string(&mut loaded_content).unwrap();
+        assert_eq!(loaded_content, "12,2019-08-27,123456789,Nuts,12.20,KG,,,\n\
+                    12,2019-08-27,987654321,Jam,1,EACH,,,\n");
+    }
+
+    #[test]
+    fn should_write_rows_of_order_at_once() {
+        let line_item = || LineItem::builder()
+            .with_product_id("123456789".to_string())
+            .with_product_name("Nuts".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
+            .build();
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_line_item(line_item())
+            .with_line_item(line_item())
+            .build();
+        let mut loader = CsvLoader::append(ChunkWriter { chunks: vec![] }).unwrap();
+
+        loader.load(order).unwrap();
+
+        match loader.writer.unwrap() {
+            Compressed::None(writer) => assert_eq!(writer.chunks.len(), 1),
+            _ => unreachable!(),
+        }
+    }
+
+    fn order(id: u64) -> Order {
+        Order::builder()
+            .with_id(id)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name("Nuts".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
+            .build()
+    }
+
+    /// Keeps every write apart.
+    struct ChunkWriter {
+        chunks: Vec<Vec<u8>>,
+    }
+
+    impl Write for ChunkWriter {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.chunks.push(buffer.to_vec());
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
     }
 }
\ No newline at end of file
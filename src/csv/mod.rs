@@ -1,2 +1,4 @@
+pub mod dialect;
 pub mod extractor;
-pub mod loader;
\ No newline at end of file
+pub mod loader;
+pub mod reporter;
\ No newline at end of file
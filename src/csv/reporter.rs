@@ -0,0 +1,351 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::io::Write;
+
+use csv::Writer;
+
+use crate::csv::dialect::Dialect;
+use crate::csv::loader::{HEADERS, row};
+use crate::extractor::ExtractorError;
+use crate::loader::DiscardedOrder;
+use crate::record::Provenance;
+use crate::reporter::Reporter;
+use crate::transformer::DiscardedRecord;
+
+const ERROR: &str = "error";
+const SOURCE: &str = "source";
+const LINE: &str = "line";
+const RAW: &str = "raw";
+
+/// Writes discards as CSV rows, so that they can be fixed and fed back in. Each discarded record becomes its raw
+/// values followed by an `error`, a `source`, a `line` and a `raw` column; each discarded order becomes a row per line
+/// item in the layout of `CsvLoader`, followed by an `error`, a `source` and a `line` column.
+///
+/// The record columns are all the ones of the discarded records, in the order they first appear (after the column
+/// names of the dialect if it has some), so that records with different columns each keep all their values. Discarded
+/// records are therefore held until the reporter is finished, or dropped, and only then written. Records which could
+/// not be extracted have no values, but keep the text of their source in the `raw` column when their extractor knows
+/// it. The `source`, `line` and `raw` text of a discarded record come from its provenance, the `line` being its id when
+/// its extractor does not know it. The `source` and `line` of a line item are the ones of its record when the order
+/// has a record per line item, and the ones of all its records, separated by spaces, otherwise.
+pub struct CsvRejectReporter<R: Write, O: Write> {
+    records: RefCell<RecordWriter<R>>,
+    orders: RefCell<Writer<O>>,
+    decimal_separator: u8,
+}
+
+/// Values of a discarded record, with its trailing fields.
+type Reject = (Vec<(String, String)>, [String; 4]);
+
+struct RecordWriter<W: Write> {
+    writer: Writer<W>,
+    has_headers: bool,
+    columns: Vec<String>,
+    /// Discarded records, until written.
+    rejects: Vec<Reject>,
+    finished: bool,
+}
+
+impl<R: Write, O: Write> CsvRejectReporter<R, O> {
+    pub fn to(records: R, orders: O) -> Result<Self, Box<dyn Error>> {
+        CsvRejectReporter::with_dialect(records, orders, &Dialect::default())
+    }
+
+    /// Writes discards in the dialect of their source, so that they can be fed back in with it; header rows are only
+    /// written when the dialect has them.
+    pub fn with_dialect(records: R, orders: O, dialect: &Dialect) -> Result<Self, Box<dyn Error>> {
+        let mut orders = dialect.writer().from_writer(orders);
+        if dialect.has_headers() {
+            orders.write_record(HEADERS.iter().chain(&[ERROR, SOURCE, LINE]))?;
+            orders.flush()?;
+        }
+        let records = RecordWriter {
+            writer: dialect.writer().from_writer(records),
+            has_headers: dialect.has_headers(),
+            columns: dialect.column_names().map_or_else(Vec::new, <[String]>::to_vec),
+            rejects: Vec::new(),
+            finished: false,
+        };
+        Ok(CsvRejectReporter { records: RefCell::new(records), orders: RefCell::new(orders), decimal_separator: dialect.decimal_separator() })
+    }
+
+    /// Writes the discarded records held so far, telling whether it fails; dropping the reporter does the same, but
+    /// silently.
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        self.records.borrow_mut().finish()
+    }
+}
+
+impl<W: Write> RecordWriter<W> {
+    /// Holds the given values, adding their columns not seen yet.
+    fn write(&mut self, values: &[(String, String)], error: &str, provenance: &Provenance, id: u64) {
+        for (column, _) in values {
+            if !self.columns.contains(column) {
+                self.columns.push(column.to_owned());
+            }
+        }
+        let trailer = [
+            error.to_string(),
+            provenance.source().unwrap_or("").to_string(),
+            provenance.line().unwrap_or(id).to_string(),
+            provenance.raw_text().unwrap_or("").to_string(),
+        ];
+        self.rejects.push((values.to_vec(), trailer));
+    }
+
+    /// Writes the header row and the held records, each value under its column and missing ones left empty.
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        if self.has_headers {
+            self.writer.write_record(self.columns.iter().map(String::as_str).chain([ERROR, SOURCE, LINE, RAW]))?;
+        }
+        for (values, trailer) in std::mem::take(&mut self.rejects) {
+            let values = self.columns.iter()
+                .map(|column| values.iter()
+                    .find(|(name, _)| name == column)
+                    .map_or("", |(_, value)| value.as_str()));
+            self.writer.write_record(values.chain(trailer.iter().map(String::as_str)))?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for RecordWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+impl<R: Write, O: Write> Reporter for CsvRejectReporter<R, O> {
+    fn report_record(&self, discarded_record: DiscardedRecord) {
+        let error = discarded_record.errors().iter().map(ToString::to_string).collect::<Vec<_>>().join("; ");
+        self.records.borrow_mut().write(discarded_record.values(), &error, discarded_record.provenance(), discarded_record.id());
+    }
+
+    fn report_order(&self, discarded_order: DiscardedOrder) {
+        let mut orders = self.orders.borrow_mut();
+        let order = discarded_order.order();
+        let provenances = discarded_order.provenances();
+        for (index, line_item) in order.line_items().iter().enumerate() {
+            let traced = match provenances.get(index) {
+                Some(provenance) if provenances.len() == order.line_items().len() => std::slice::from_ref(provenance),
+                _ => provenances,
+            };
+            let (source, line) = traces(traced);
+            let _ = orders.write_record(row(order, line_item, self.decimal_separator).iter().map(String::as_str)
+                .chain([discarded_order.error_message(), &source, &line]));
+        }
+        let _ = orders.flush();
+    }
+
+    fn report_extraction(&self, extractor_error: ExtractorError) {
+        self.records.borrow_mut().write(&[], extractor_error.message(), extractor_error.provenance(), extractor_error.position());
+    }
+}
+
+/// Distinct sources and lines of the given provenances, each separated by spaces.
+fn traces(provenances: &[Provenance]) -> (String, String) {
+    let mut sources: Vec<&str> = Vec::new();
+    for source in provenances.iter().filter_map(Provenance::source) {
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+    let lines: Vec<String> = provenances.iter().filter_map(Provenance::line).map(|line| line.to_string()).collect();
+    (sources.join(" "), lines.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, SeekFrom};
+
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+    use tempfile::tempfile;
+
+    use crate::loader::OrderErrorKind;
+    use crate::order::{Order, Quantity, Unit};
+    use crate::record::MapRecord;
+    use crate::transformer::{FieldError, RecordErrorKind};
+
+    use super::*;
+
+    #[test]
+    fn should_write_discarded_records_with_their_values() {
+        let reporter = CsvRejectReporter::to(vec![], vec![]).unwrap();
+
+        reporter.report_record(DiscardedRecord::with_errors(&record(2, "2019", "Nuts"), vec![
+            FieldError::new("date", RecordErrorKind::Malformed, "Invalid date.".to_string()),
+            FieldError::new("quantity", RecordErrorKind::Invalid, "Invalid count.".to_string()),
+        ]));
+        reporter.report_record(DiscardedRecord::new(&record(5, "2020", "Jam, Strawberry"), RecordErrorKind::Invalid, "Invalid record.".to_string()));
+
+        assert_eq!(records(reporter), "Year,Product Name,error,source,line,raw\n\
+                                       2019,Nuts,date: Invalid date.; quantity: Invalid count.,,2,\n\
+                                       2020,\"Jam, Strawberry\",Invalid record.,,5,\n");
+    }
+
+    #[test]
+    fn should_write_failed_extractions_without_values() {
+        let reporter = CsvRejectReporter::to(vec![], vec![]).unwrap();
+
+        reporter.report_extraction(ExtractorError::at(1, "unreadable record")
+            .with_provenance(Provenance::default().with_line(2).with_raw_text("2018,\"Salt")));
+        reporter.report_record(DiscardedRecord::new(&record(2, "2019", "Nuts"), RecordErrorKind::Invalid, "Invalid record.".to_string()));
+        reporter.report_extraction(ExtractorError::at(3, "unreadable record").with_location(4, 60));
+        reporter.report_extraction(ExtractorError::at(5, "unreadable record"));
+
+        assert_eq!(records(reporter), "Year,Product Name,error,source,line,raw\n\
+                                       ,,unreadable record,,2,\"2018,\"\"Salt\"\n\
+                                       2019,Nuts,Invalid record.,,2,\n\
+                                       ,,unreadable record,,4,\n\
+                                       ,,unreadable record,,5,\n");
+    }
+
+    #[test]
+    fn should_write_rejects_once_dropped() {
+        let file = tempfile().unwrap();
+        let mut cloned = file.try_clone().unwrap();
+        let reporter = CsvRejectReporter::to(file, vec![]).unwrap();
+
+        reporter.report_extraction(ExtractorError::at(3, "unreadable record").with_location(4, 60));
+        drop(reporter);
+
+        let mut written = String::new();
+        cloned.seek(SeekFrom::Start(0)).unwrap();
+        cloned.read_to_string(&mut written).unwrap();
+        assert_eq!(written, "error,source,line,raw\n\
+                             unreadable record,,4,\n");
+    }
+
+    #[test]
+    fn should_write_all_columns_of_discarded_records() {
+        let reporter = CsvRejectReporter::to(vec![], vec![]).unwrap();
+
+        reporter.report_record(DiscardedRecord::new(&record(2, "2019", "Nuts"), RecordErrorKind::Invalid, "Invalid record.".to_string()));
+        reporter.report_record(DiscardedRecord::new(&MapRecord::with_columns(3, vec![
+            ("Product Name".to_string(), "Jam".to_string()),
+            ("Count".to_string(), "many".to_string()),
+        ]), RecordErrorKind::Invalid, "Invalid record.".to_string()));
+
+        assert_eq!(records(reporter), "Year,Product Name,Count,error,source,line,raw\n\
+                                       2019,Nuts,,Invalid record.,,2,\n\
+                                       ,Jam,many,Invalid record.,,3,\n");
+    }
+
+    #[test]
+    fn should_write_rejects_in_dialect() {
+        let dialect = Dialect::default()
+            .with_delimiter(b';')
+            .with_headers(false)
+            .with_column_names(vec!["Product Name".to_string(), "Year".to_string()])
+            .with_decimal_separator(b',');
+        let reporter = CsvRejectReporter::with_dialect(vec![], vec![], &dialect).unwrap();
+
+        reporter.report_record(DiscardedRecord::new(&record(2, "2019", "Nuts"), RecordErrorKind::Invalid, "Invalid record.".to_string()));
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name("Jam".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
+            .build();
+        reporter.report_order(DiscardedOrder::new(order, OrderErrorKind::Io, "Disk full.".to_string()));
+
+        let orders = String::from_utf8(reporter.orders.borrow_mut().get_ref().clone()).unwrap();
+        assert_eq!(records(reporter), "Nuts;2019;Invalid record.;;2;\n");
+        assert_eq!(orders, "12;2019-08-27;123456789;Jam;12,20;KG;;;;Disk full.;;\n");
+    }
+
+    #[test]
+    fn should_write_provenance_of_discarded_records() {
+        let reporter = CsvRejectReporter::to(vec![], vec![]).unwrap();
+        let provenance = Provenance::default().with_source("stores/east.csv").with_line(7);
+
+        reporter.report_record(DiscardedRecord::new(&record(2, "2019", "Nuts").with_provenance(provenance.clone()), RecordErrorKind::Invalid, "Invalid record.".to_string()));
+        reporter.report_extraction(ExtractorError::at(3, "unreadable record").with_provenance(provenance.with_line(8)));
+
+        assert_eq!(records(reporter), "Year,Product Name,error,source,line,raw\n\
+                                       2019,Nuts,Invalid record.,stores/east.csv,7,\n\
+                                       ,,unreadable record,stores/east.csv,8,\n");
+    }
+
+    #[test]
+    fn should_write_discarded_orders_with_their_line_items() {
+        let reporter = CsvRejectReporter::to(vec![], vec![]).unwrap();
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name("Nuts".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
+            .build()
+            .with_provenance(Provenance::default().with_source("stores/east.csv").with_line(7));
+
+        reporter.report_order(DiscardedOrder::new(order, OrderErrorKind::Duplicate, "Order already exists.".to_string()));
+
+        let orders = String::from_utf8(reporter.orders.into_inner().into_inner().unwrap()).unwrap();
+        assert_eq!(orders, "Order Id,Date Time,Product Id,Product Name,Quantity,Unit,Unit Price,Currency,Total,error,source,line\n\
+                            12,2019-08-27,123456789,Nuts,12.20,KG,,,,Order already exists.,stores/east.csv,7\n");
+    }
+
+    #[test]
+    fn should_trace_line_items_of_aggregated_orders_to_their_records() {
+        let reporter = CsvRejectReporter::to(vec![], vec![]).unwrap();
+        let mut order = line_item_order("Nuts").with_provenance(Provenance::default().with_line(2));
+        order.merge(line_item_order("Jam").with_provenance(Provenance::default().with_line(4))).unwrap();
+
+        reporter.report_order(DiscardedOrder::new(order, OrderErrorKind::Io, "Disk full.".to_string()));
+
+        let orders = String::from_utf8(reporter.orders.into_inner().into_inner().unwrap()).unwrap();
+        assert!(orders.ends_with("12,2019-08-27,123456789,Nuts,1,EACH,,,,Disk full.,,2\n\
+                                  12,2019-08-27,123456789,Jam,1,EACH,,,,Disk full.,,4\n"));
+    }
+
+    #[test]
+    fn should_trace_line_items_to_all_records_of_order_otherwise() {
+        let reporter = CsvRejectReporter::to(vec![], vec![]).unwrap();
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name("Nuts".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::ONE).with_unit(Unit::EACH).build())
+            .build()
+            .with_provenance(Provenance::default().with_source("orders.json").with_line(2))
+            .with_provenance(Provenance::default().with_source("orders.json").with_line(3));
+
+        reporter.report_order(DiscardedOrder::new(order, OrderErrorKind::Io, "Disk full.".to_string()));
+
+        let orders = String::from_utf8(reporter.orders.into_inner().into_inner().unwrap()).unwrap();
+        assert!(orders.ends_with("12,2019-08-27,123456789,Nuts,1,EACH,,,,Disk full.,orders.json,2 3\n"));
+    }
+
+    fn line_item_order(product_name: &str) -> Order {
+        Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name(product_name.to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::ONE).with_unit(Unit::EACH).build())
+            .build()
+    }
+
+    fn record(id: u64, year: &str, product_name: &str) -> MapRecord {
+        MapRecord::with_columns(id, vec![
+            ("Year".to_string(), year.to_string()),
+            ("Product Name".to_string(), product_name.to_string()),
+        ])
+    }
+
+    fn records(reporter: CsvRejectReporter<Vec<u8>, Vec<u8>>) -> String {
+        let mut records = reporter.records.into_inner();
+        records.finish().unwrap();
+        let writer = std::mem::replace(&mut records.writer, Writer::from_writer(vec![]));
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+use std::io::{self, Chain, Cursor, Read};
+use std::str::FromStr;
+
+use chardetng::EncodingDetector;
+use encoding_rs::UTF_8;
+use encoding_rs_io::{DecodeReaderBytes, DecodeReaderBytesBuilder};
+
+/// Number of bytes the encoding of an input is guessed from.
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+/// Character encoding of an input, which is transcoded to UTF-8 as it is read. A byte order mark always takes
+/// precedence, so UTF-16 inputs starting with one are decoded whatever the encoding.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Encoding {
+    /// Guesses the encoding from the beginning of the input.
+    Detect,
+    /// Decodes with the given encoding. UTF-8 is passed through as is, invalid sequences included.
+    Fixed(&'static encoding_rs::Encoding),
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Fixed(UTF_8)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct UnknownEncoding(String);
+
+impl fmt::Display for UnknownEncoding {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "unknown encoding {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownEncoding {}
+
+/// Parses `detect` or a WHATWG encoding label, e.g. `windows-1252`, `latin1` or `utf-16le`.
+impl FromStr for Encoding {
+    type Err = UnknownEncoding;
+
+    fn from_str(label: &str) -> Result<Self, Self::Err> {
+        if label.eq_ignore_ascii_case("detect") {
+            return Ok(Encoding::Detect);
+        }
+        encoding_rs::Encoding::for_label(label.as_bytes())
+            .map(Encoding::Fixed)
+            .ok_or_else(|| UnknownEncoding(label.to_string()))
+    }
+}
+
+pub(crate) type Decoded<R> = DecodeReaderBytes<Chain<Cursor<Vec<u8>>, R>, Vec<u8>>;
+
+/// Wraps a reader into one yielding UTF-8. Detecting the encoding reads a sample of the input first, which is then
+/// replayed ahead of the rest.
+pub(crate) fn decode<R: Read>(mut reader: R, encoding: Encoding) -> io::Result<Decoded<R>> {
+    let mut sample = Vec::new();
+    let encoding = match encoding {
+        Encoding::Fixed(encoding) => encoding,
+        Encoding::Detect => {
+            (&mut reader).take(SAMPLE_SIZE).read_to_end(&mut sample)?;
+            let mut detector = EncodingDetector::new();
+            detector.feed(&sample, (sample.len() as u64) < SAMPLE_SIZE);
+            detector.guess(None, true)
+        }
+    };
+    Ok(DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding))
+        .bom_override(true)
+        .strip_bom(true)
+        .utf8_passthru(true)
+        .build(Cursor::new(sample).chain(reader)))
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::WINDOWS_1252;
+
+    use super::*;
+
+    #[test]
+    fn should_parse_labels() {
+        assert_eq!("detect".parse(), Ok(Encoding::Detect));
+        assert_eq!("latin1".parse(), Ok(Encoding::Fixed(WINDOWS_1252)));
+        assert_eq!("UTF-8".parse(), Ok(Encoding::Fixed(UTF_8)));
+        assert_eq!("klingon".parse::<Encoding>(), Err(UnknownEncoding("klingon".to_string())));
+    }
+
+    #[test]
+    fn should_decode_with_fixed_encoding() {
+        assert_eq!(decoded(b"Cr\xe8me br\xfbl\xe9e", "windows-1252".parse().unwrap()), "Crème brûlée");
+    }
+
+    #[test]
+    fn should_decode_utf16_with_bom_whatever_the_encoding() {
+        let bytes: Vec<u8> = [0xff, 0xfe].iter().copied()
+            .chain("Crème".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+
+        assert_eq!(decoded(&bytes, Encoding::default()), "Crème");
+    }
+
+    #[test]
+    fn should_strip_utf8_bom() {
+        assert_eq!(decoded(b"\xef\xbb\xbfOrder Number", Encoding::default()), "Order Number");
+    }
+
+    #[test]
+    fn should_detect_encoding() {
+        let text = "Order Number,Product Name\n13,Crème brûlée à la française\n16,Pâté de campagne\n";
+        let (bytes, _, _) = WINDOWS_1252.encode(text);
+
+        assert_eq!(decoded(&bytes, Encoding::Detect), text);
+        assert_eq!(decoded(text.as_bytes(), Encoding::Detect), text);
+    }
+
+    fn decoded(bytes: &[u8], encoding: Encoding) -> String {
+        let mut decoded = String::new();
+        decode(bytes, encoding).unwrap().read_to_string(&mut decoded).unwrap();
+        decoded
+    }
+}
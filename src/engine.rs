@@ -1,25 +1,937 @@
-use crate::reporter::Reporter;
-use crate::extractor::Extractor;
-use crate::loader::Loader;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::checkpoint::Checkpoint;
+use crate::extractor::{Extractor, ExtractorError};
+use crate::loader::{DiscardedOrder, Loader, OrderErrorKind};
+use crate::order::Order;
 use crate::record::Record;
-use crate::transformer::Transformer;
+use crate::report::RunReport;
+use crate::reporter::Reporter;
+use crate::transformer::{DiscardedRecord, Transformer};
+
+const BATCH_SIZE: usize = 64;
+
+const CONFLICTING_DATE: &str = "Order date differs from the one of its other line items.";
+
+pub struct Engine {
+    workers: usize,
+    checkpoint: Option<PathBuf>,
+    threshold: DiscardThreshold,
+    aggregation: Aggregation,
+}
 
-pub struct Engine {}
+/// How the orders transformed from several records are grouped, by id, into multi-line orders before loading.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Aggregation {
+    /// Orders are loaded as transformed.
+    None,
+    /// Runs of orders with the same id are merged.
+    Consecutive,
+    /// All the orders with the same id are merged wherever they are in the source. Orders are held until the
+    /// extraction ends and then loaded in the order their id first appeared.
+    Keyed,
+}
 
 impl Engine {
-    pub fn etl<R: Record>(extractor: &mut dyn Extractor<R>,
-                          transformer: &dyn Transformer<R>,
-                          reporter: &dyn Reporter,
-                          loader: &mut dyn Loader) {
-        extractor.into_iter()
-            .map(|record| transformer.transform(record))
-            .for_each(|order| {
-                match order {
-                    Ok(order) => if let Err(discarded_order) = loader.load(order) {
-                        reporter.report_order(discarded_order);
-                    },
-                    Err(discarded_record) => reporter.report_record(discarded_record),
-                }
-            });
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder {
+            workers: 1,
+            checkpoint: None,
+            threshold: DiscardThreshold::default(),
+            aggregation: Aggregation::None,
+        }
+    }
+
+    pub fn etl<R: Record + Send>(extractor: &mut dyn Extractor<R>,
+                                 transformer: &(dyn Transformer<R> + Sync),
+                                 reporter: &dyn Reporter,
+                                 loader: &mut dyn Loader) -> Result<RunReport, EngineError> {
+        Engine::builder().build().run(extractor, transformer, reporter, loader)
+    }
+
+    /// Runs the pipeline. With a checkpoint configured, records already committed by an interrupted run are skipped
+    /// (record ids are expected to grow in source order) and the checkpoint is removed once the run completes. The
+    /// checkpoint is only written every few records, once the loader is flushed, so a resumed run may load again the
+    /// last records of the interrupted one.
+    ///
+    /// Exceeding the discard threshold aborts the loader and the run; the checkpoint is then removed as well, since
+    /// the output is either rolled back or incomplete. Any other failure aborts the loader too, unless a checkpoint is
+    /// kept for the run to be resumed, which needs the output of the failed one.
+    ///
+    /// When aggregating, an order is committed only once loaded, so a resumed run extracts all its records again.
+    /// Merged orders should share the same date, a conflicting one being discarded.
+    pub fn run<R: Record + Send>(&self,
+                                 extractor: &mut dyn Extractor<R>,
+                                 transformer: &(dyn Transformer<R> + Sync),
+                                 reporter: &dyn Reporter,
+                                 loader: &mut dyn Loader) -> Result<RunReport, EngineError> {
+        let start = Instant::now();
+        let checkpoint = match &self.checkpoint {
+            Some(path) => Some(Checkpoint::open(path).map_err(EngineError::Checkpoint)?),
+            None => None,
+        };
+        let mut extraction = Extraction::of(extractor, checkpoint.as_ref().and_then(Checkpoint::last_id));
+        let mut sink = Sink {
+            reporter,
+            loader,
+            checkpoint,
+            threshold: self.threshold,
+            report: RunReport::new(),
+            aggregation: self.aggregation,
+            pending: Vec::new(),
+            positions: HashMap::new(),
+            last_id: None,
+            uncommitted: 0,
+        };
+
+        let processed = if self.workers > 1 {
+            self.run_parallel(&mut extraction, transformer, &mut sink)
+        } else {
+            extraction.by_ref().try_for_each(|record| sink.accept(transform(transformer, record)))
+        };
+        let processed = processed
+            .and_then(|()| sink.flush())
+            .and_then(|()| sink.finish());
+
+        let mut report = std::mem::take(&mut sink.report);
+        report.extraction(extraction.extracted, extraction.skipped, extraction.time);
+        report.finished(start.elapsed());
+        match processed {
+            Ok(()) => {
+                if let Some(checkpoint) = sink.checkpoint {
+                    checkpoint.clear().map_err(EngineError::Checkpoint)?;
+                }
+                Ok(report)
+            }
+            Err(EngineError::DiscardThresholdExceeded(_)) => {
+                sink.loader.abort();
+                if let Some(checkpoint) = sink.checkpoint {
+                    checkpoint.clear().map_err(EngineError::Checkpoint)?;
+                }
+                Err(EngineError::DiscardThresholdExceeded(Box::new(report)))
+            }
+            Err(e) => {
+                if sink.checkpoint.is_none() {
+                    sink.loader.abort();
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Transforms batches of records on a pool of worker threads while extraction and loading stay on the calling
+    /// thread. Transformed batches are buffered until all the preceding ones are done, so orders reach the loader
+    /// (and discards the reporter) in source order.
+    fn run_parallel<R: Record + Send>(&self,
+                                      extractor: &mut dyn Iterator<Item=Result<R, ExtractorError>>,
+                                      transformer: &(dyn Transformer<R> + Sync),
+                                      sink: &mut Sink) -> Result<(), EngineError> {
+        let (batch_sender, batch_receiver) = mpsc::channel::<(usize, Vec<Result<R, ExtractorError>>)>();
+        let batch_receiver = Mutex::new(batch_receiver);
+        let (result_sender, result_receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..self.workers {
+                let batch_receiver = &batch_receiver;
+                let result_sender = result_sender.clone();
+                scope.spawn(move || loop {
+                    let batch = batch_receiver.lock().unwrap().recv();
+                    let (sequence, records) = match batch {
+                        Ok(batch) => batch,
+                        Err(_) => break,
+                    };
+                    let outcomes = panic::catch_unwind(AssertUnwindSafe(|| records.into_iter()
+                        .map(|record| transform(transformer, record))
+                        .collect::<Vec<_>>()));
+                    if result_sender.send((sequence, outcomes)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(result_sender);
+
+            let batch_sender = batch_sender;
+            let mut batches = OrderedBatches::new();
+            let mut sent = 0;
+            loop {
+                let batch: Vec<_> = (&mut *extractor).take(BATCH_SIZE).collect();
+                if batch.is_empty() {
+                    break;
+                }
+                while sent - batches.received() >= 2 * self.workers {
+                    batches.receive(&result_receiver, sink)?;
+                }
+                batch_sender.send((sent, batch)).expect("transform workers terminated");
+                sent += 1;
+            }
+            drop(batch_sender);
+            while batches.received() < sent {
+                batches.receive(&result_receiver, sink)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+pub struct EngineBuilder {
+    workers: usize,
+    checkpoint: Option<PathBuf>,
+    threshold: DiscardThreshold,
+    aggregation: Aggregation,
+}
+
+impl EngineBuilder {
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        assert!(workers > 0, "workers should be > 0");
+        self.workers = workers;
+        self
+    }
+
+    pub fn with_checkpoint<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.checkpoint = Some(path.into());
+        self
+    }
+
+    /// Aborts the run once more than the given number of records and orders have been discarded.
+    pub fn with_max_discards(mut self, count: u64) -> Self {
+        self.threshold.count = Some(count);
+        self
+    }
+
+    /// Aborts the run once more than the given fraction (0 to 1) of the processed records has been discarded; the
+    /// ratio is only checked after the given minimum of records has been processed.
+    pub fn with_max_discard_ratio(mut self, ratio: f64, minimum: u64) -> Self {
+        assert!((0.0..=1.0).contains(&ratio), "ratio should be between 0 and 1");
+        self.threshold.ratio = Some((ratio, minimum));
+        self
+    }
+
+    pub fn with_aggregation(mut self, aggregation: Aggregation) -> Self {
+        self.aggregation = aggregation;
+        self
+    }
+
+    pub fn build(self) -> Engine {
+        Engine {
+            workers: self.workers,
+            checkpoint: self.checkpoint,
+            threshold: self.threshold,
+            aggregation: self.aggregation,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EngineError {
+    Checkpoint(io::Error),
+    Loader(Box<dyn Error>),
+    DiscardThresholdExceeded(Box<RunReport>),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::Checkpoint(e) => write!(formatter, "checkpoint failure: {}", e),
+            EngineError::Loader(e) => write!(formatter, "loader failure: {}", e),
+            EngineError::DiscardThresholdExceeded(report) => write!(formatter, "discard threshold exceeded: {} of {} records and {} orders discarded",
+                                                                    report.discarded_records() + report.failed_extractions(), report.extracted(),
+                                                                    report.discarded_orders()),
+        }
+    }
+}
+
+impl Error for EngineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EngineError::Checkpoint(e) => Some(e),
+            EngineError::Loader(e) => Some(e.as_ref()),
+            EngineError::DiscardThresholdExceeded(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct DiscardThreshold {
+    count: Option<u64>,
+    ratio: Option<(f64, u64)>,
+}
+
+impl DiscardThreshold {
+    fn is_exceeded(&self, report: &RunReport) -> bool {
+        let discarded = report.discarded_records() + report.failed_extractions() + report.discarded_orders();
+        let processed = report.transformed() + report.discarded_records() + report.failed_extractions();
+        self.count.is_some_and(|count| discarded > count)
+            || self.ratio.is_some_and(|(ratio, minimum)| processed >= minimum && discarded as f64 > ratio * processed as f64)
+    }
+}
+
+/// Pulls records from the extractor, skipping the ones at or below the last committed id.
+struct Extraction<'a, R> {
+    extractor: &'a mut dyn Extractor<R>,
+    last_id: Option<u64>,
+    extracted: u64,
+    skipped: u64,
+    time: Duration,
+}
+
+impl<'a, R: Record> Extraction<'a, R> {
+    fn of(extractor: &'a mut dyn Extractor<R>, last_id: Option<u64>) -> Self {
+        Extraction { extractor, last_id, extracted: 0, skipped: 0, time: Duration::default() }
+    }
+}
+
+impl<'a, R: Record> Iterator for Extraction<'a, R> {
+    type Item = Result<R, ExtractorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = Instant::now();
+            let record = self.extractor.next();
+            self.time += start.elapsed();
+            match record {
+                Some(record) if self.last_id.is_some_and(|last_id| id_of(&record) <= last_id) => self.skipped += 1,
+                Some(record) => {
+                    self.extracted += 1;
+                    return Some(record);
+                }
+                None => return None,
+            }
+        }
+    }
+}
+
+/// Outcome of transforming a single record.
+struct Outcome {
+    id: u64,
+    transformed: Transformed,
+    time: Duration,
+}
+
+enum Transformed {
+    Order(Order),
+    DiscardedRecord(DiscardedRecord),
+    FailedExtraction(ExtractorError),
+}
+
+fn id_of<R: Record>(record: &Result<R, ExtractorError>) -> u64 {
+    record.as_ref().map_or_else(ExtractorError::position, Record::id)
+}
+
+/// Failed extractions are passed through untransformed, with the position of the failure as their id. Orders keep the
+/// provenance of their record, so that they can be traced back to it when discarded.
+fn transform<R: Record>(transformer: &(dyn Transformer<R> + Sync), record: Result<R, ExtractorError>) -> Outcome {
+    let start = Instant::now();
+    let id = id_of(&record);
+    let transformed = match record.map(|record| {
+        let provenance = record.provenance().clone();
+        transformer.transform(record).map(|order| order.with_provenance(provenance))
+    }) {
+        Ok(Ok(order)) => Transformed::Order(order),
+        Ok(Err(discarded_record)) => Transformed::DiscardedRecord(discarded_record),
+        Err(e) => Transformed::FailedExtraction(e),
+    };
+    Outcome { id, transformed, time: start.elapsed() }
+}
+
+/// Receives outcomes in source order, hands them over to the loader or the reporter and then commits them. Aggregated
+/// orders are held in `pending` until complete, and records are committed only once no order is pending. The
+/// checkpoint is written once every `BATCH_SIZE` records, after the loader has been flushed.
+struct Sink<'a> {
+    reporter: &'a dyn Reporter,
+    loader: &'a mut dyn Loader,
+    checkpoint: Option<Checkpoint>,
+    threshold: DiscardThreshold,
+    report: RunReport,
+    aggregation: Aggregation,
+    pending: Vec<Order>,
+    positions: HashMap<u64, usize>,
+    last_id: Option<u64>,
+    /// Records accepted since the checkpoint was last written.
+    uncommitted: usize,
+}
+
+impl<'a> Sink<'a> {
+    fn accept(&mut self, outcome: Outcome) -> Result<(), EngineError> {
+        self.report.transformation(outcome.time);
+        match outcome.transformed {
+            Transformed::Order(order) => {
+                self.report.transformed_record();
+                self.aggregate(order)?;
+            }
+            Transformed::DiscardedRecord(discarded_record) => {
+                self.report.discarded_record(discarded_record.errors());
+                self.reporter.report_record(discarded_record);
+            }
+            Transformed::FailedExtraction(extractor_error) => {
+                self.report.failed_extraction(extractor_error.category());
+                self.reporter.report_extraction(extractor_error);
+            }
+        }
+        self.check_threshold()?;
+        self.last_id = Some(outcome.id);
+        self.uncommitted += 1;
+        if self.pending.is_empty() {
+            self.commit(outcome.id)?;
+        }
+        Ok(())
+    }
+
+    fn aggregate(&mut self, order: Order) -> Result<(), EngineError> {
+        match self.aggregation {
+            Aggregation::None => self.load(order),
+            Aggregation::Consecutive => match self.pending.last_mut() {
+                Some(pending) if pending.id() == order.id() => self.merge(0, order),
+                _ => {
+                    // the records preceding this one are complete once the pending order is loaded
+                    if let Some(pending) = self.pending.pop() {
+                        self.load(pending);
+                        if let Some(last_id) = self.last_id {
+                            self.commit(last_id)?;
+                        }
+                    }
+                    self.pending.push(order);
+                }
+            },
+            Aggregation::Keyed => match self.positions.get(&order.id()) {
+                Some(&position) => self.merge(position, order),
+                None => {
+                    self.positions.insert(order.id(), self.pending.len());
+                    self.pending.push(order);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, position: usize, order: Order) {
+        if let Err(order) = self.pending[position].merge(order) {
+            self.discard(DiscardedOrder::new(order, OrderErrorKind::Conflict, CONFLICTING_DATE.to_string()));
+        }
+    }
+
+    /// Loads the orders still pending once the extraction is over.
+    fn flush(&mut self) -> Result<(), EngineError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.positions.clear();
+        for order in std::mem::take(&mut self.pending) {
+            self.load(order);
+        }
+        self.check_threshold()?;
+        match self.last_id {
+            Some(last_id) => self.commit(last_id),
+            None => Ok(()),
+        }
+    }
+
+    fn load(&mut self, order: Order) {
+        let start = Instant::now();
+        let loaded = self.loader.load(order);
+        self.report.loading(start.elapsed());
+        match loaded {
+            Ok(()) => self.report.loaded_order(),
+            Err(discarded_order) => self.discard(discarded_order),
+        }
+        self.collect_rolled_back();
+    }
+
+    fn finish(&mut self) -> Result<(), EngineError> {
+        let finished = self.loader.finish().map_err(EngineError::Loader);
+        self.collect_rolled_back();
+        finished
+    }
+
+    /// Discards the orders the loader lost after loading them.
+    fn collect_rolled_back(&mut self) {
+        for discarded_order in self.loader.rolled_back() {
+            self.report.rolled_back_order(discarded_order.kind(), discarded_order.error_message());
+            self.reporter.report_order(discarded_order);
+        }
+    }
+
+    fn discard(&mut self, discarded_order: DiscardedOrder) {
+        self.report.discarded_order(discarded_order.kind(), discarded_order.error_message());
+        self.reporter.report_order(discarded_order);
+    }
+
+    fn check_threshold(&self) -> Result<(), EngineError> {
+        if self.threshold.is_exceeded(&self.report) {
+            return Err(EngineError::DiscardThresholdExceeded(Box::default()));
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self, id: u64) -> Result<(), EngineError> {
+        if self.checkpoint.is_none() || self.uncommitted < BATCH_SIZE {
+            return Ok(());
+        }
+        self.loader.flush().map_err(EngineError::Loader)?;
+        self.collect_rolled_back();
+        self.check_threshold()?;
+        if let Some(checkpoint) = &mut self.checkpoint {
+            checkpoint.commit(id).map_err(EngineError::Checkpoint)?;
+        }
+        self.uncommitted = 0;
+        Ok(())
+    }
+}
+
+type TransformedBatch = (usize, thread::Result<Vec<Outcome>>);
+
+struct OrderedBatches {
+    pending: BTreeMap<usize, Vec<Outcome>>,
+    next_sequence: usize,
+}
+
+impl OrderedBatches {
+    fn new() -> Self {
+        OrderedBatches { pending: BTreeMap::new(), next_sequence: 0 }
+    }
+
+    fn received(&self) -> usize {
+        self.next_sequence + self.pending.len()
+    }
+
+    fn receive(&mut self, receiver: &mpsc::Receiver<TransformedBatch>, sink: &mut Sink) -> Result<(), EngineError> {
+        let (sequence, outcomes) = receiver.recv().expect("transform workers terminated");
+        match outcomes {
+            Ok(outcomes) => self.pending.insert(sequence, outcomes),
+            Err(cause) => panic::resume_unwind(cause),
+        };
+        while let Some(outcomes) = self.pending.remove(&self.next_sequence) {
+            for outcome in outcomes {
+                sink.accept(outcome)?;
+            }
+            self.next_sequence += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::thread;
+    use std::time::Duration;
+
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+    use tempfile::tempdir;
+
+    use crate::loader::DiscardedOrder;
+    use crate::order::Quantity;
+    use crate::record::{MapRecord, Provenance};
+    use crate::transformer::RecordErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn should_load_in_source_order_when_transforming_in_parallel() {
+        let mut extractor = VecExtractor::of(1000);
+        let reporter = CollectingReporter::new();
+        let mut loader = CollectingLoader::new();
+
+        Engine::builder().with_workers(4).build()
+            .run(&mut extractor, &SlowTransformer {}, &reporter, &mut loader).unwrap();
+
+        let expected_ids: Vec<u64> = (1..=1000u64).filter(|id| !id.is_multiple_of(10)).collect();
+        let expected_discarded_ids: Vec<u64> = (1..=1000u64).filter(|id| id.is_multiple_of(10)).collect();
+        assert_eq!(loader.ids, expected_ids);
+        assert_eq!(reporter.record_ids.into_inner(), expected_discarded_ids);
+    }
+
+    #[test]
+    fn should_load_the_same_orders_serially_and_in_parallel() {
+        let serial_reporter = CollectingReporter::new();
+        let mut serial_loader = CollectingLoader::new();
+        Engine::etl(&mut VecExtractor::of(300), &SlowTransformer {}, &serial_reporter, &mut serial_loader).unwrap();
+
+        let parallel_reporter = CollectingReporter::new();
+        let mut parallel_loader = CollectingLoader::new();
+        Engine::builder().with_workers(3).build()
+            .run(&mut VecExtractor::of(300), &SlowTransformer {}, &parallel_reporter, &mut parallel_loader).unwrap();
+
+        assert_eq!(parallel_loader.ids, serial_loader.ids);
+        assert_eq!(parallel_reporter.record_ids, serial_reporter.record_ids);
+    }
+
+    #[test]
+    fn should_report_run_statistics() {
+        let mut loader = FailingLoader { remaining: 100 };
+
+        let report = Engine::builder().with_workers(2).build()
+            .run(&mut VecExtractor::of(20), &SlowTransformer {}, &CollectingReporter::new(), &mut loader).unwrap();
+
+        assert_eq!(report.extracted(), 20);
+        assert_eq!(report.skipped(), 0);
+        assert_eq!(report.transformed(), 18);
+        assert_eq!(report.discarded_records(), 2);
+        assert_eq!(report.record_discards().get("Invalid record."), Some(&2));
+        assert_eq!(report.loaded(), 18);
+        assert_eq!(report.discarded_orders(), 0);
+        assert!(report.transformation_time() > Duration::default());
+    }
+
+    #[test]
+    fn should_report_failed_extractions() {
+        let reporter = CollectingReporter::new();
+        let mut loader = CollectingLoader::new();
+
+        let report = Engine::builder().with_workers(3).build()
+            .run(&mut VecExtractor::failing(30, |id| id % 4 == 0), &SlowTransformer {}, &reporter, &mut loader).unwrap();
+
+        assert_eq!(*reporter.extraction_positions.borrow(), vec![4, 8, 12, 16, 20, 24, 28]);
+        assert_eq!(*reporter.record_ids.borrow(), vec![10, 30]);
+        assert_eq!(report.extracted(), 30);
+        assert_eq!(report.failed_extractions(), 7);
+        assert_eq!(report.extraction_discards().get("unreadable record"), Some(&7));
+        assert_eq!(report.discarded_records(), 2);
+        assert_eq!(loader.ids.len(), 21);
+    }
+
+    #[test]
+    fn should_abort_when_discards_exceed_count() {
+        let mut loader = CollectingLoader::new();
+
+        let result = Engine::builder().with_max_discards(2).build()
+            .run(&mut VecExtractor::of(100), &SlowTransformer {}, &CollectingReporter::new(), &mut loader);
+
+        match result {
+            Err(EngineError::DiscardThresholdExceeded(report)) => {
+                assert_eq!(report.discarded_records(), 3);
+                assert_eq!(report.extracted(), 30);
+            }
+            _ => panic!("Discard threshold should be exceeded!"),
+        }
+        assert_eq!(loader.ids.len(), 27);
+        assert!(loader.aborted);
+        assert!(!loader.finished);
+    }
+
+    #[test]
+    fn should_abort_loader_when_run_fails() {
+        let mut loader = UnfinishableLoader { aborted: false };
+
+        let result = Engine::builder().build()
+            .run(&mut VecExtractor::of(10), &SlowTransformer {}, &CollectingReporter::new(), &mut loader);
+
+        assert!(matches!(result, Err(EngineError::Loader(_))));
+        assert!(loader.aborted);
+    }
+
+    #[test]
+    fn should_keep_output_of_failed_run_to_resume() {
+        let directory = tempdir().unwrap();
+        let mut loader = UnfinishableLoader { aborted: false };
+
+        let result = Engine::builder().with_checkpoint(directory.path().join("checkpoint")).build()
+            .run(&mut VecExtractor::of(10), &SlowTransformer {}, &CollectingReporter::new(), &mut loader);
+
+        assert!(matches!(result, Err(EngineError::Loader(_))));
+        assert!(!loader.aborted);
+    }
+
+    #[test]
+    fn should_abort_when_discards_exceed_ratio() {
+        let mut loader = CollectingLoader::new();
+
+        let result = Engine::builder().with_workers(2).with_max_discard_ratio(0.05, 20).build()
+            .run(&mut VecExtractor::of(1000), &SlowTransformer {}, &CollectingReporter::new(), &mut loader);
+
+        match result {
+            Err(EngineError::DiscardThresholdExceeded(report)) => assert_eq!(report.discarded_records(), 2),
+            _ => panic!("Discard threshold should be exceeded!"),
+        }
+        assert!(loader.aborted);
+    }
+
+    #[test]
+    fn should_finish_when_discards_within_threshold() {
+        let mut loader = CollectingLoader::new();
+
+        let report = Engine::builder().with_max_discards(10).with_max_discard_ratio(0.1, 0).build()
+            .run(&mut VecExtractor::of(100), &SlowTransformer {}, &CollectingReporter::new(), &mut loader).unwrap();
+
+        assert_eq!(report.discarded_records(), 10);
+        assert!(loader.finished);
+        assert!(!loader.aborted);
+    }
+
+    #[test]
+    fn should_skip_records_committed_by_previous_run() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("checkpoint");
+        Checkpoint::open(&path).unwrap().commit(250).unwrap();
+        let reporter = CollectingReporter::new();
+        let mut loader = CollectingLoader::new();
+
+        let report = Engine::builder().with_workers(2).with_checkpoint(&path).build()
+            .run(&mut VecExtractor::of(300), &SlowTransformer {}, &reporter, &mut loader).unwrap();
+
+        let expected_ids: Vec<u64> = (251..=300u64).filter(|id| !id.is_multiple_of(10)).collect();
+        assert_eq!(loader.ids, expected_ids);
+        assert_eq!(reporter.record_ids.into_inner(), vec![260, 270, 280, 290, 300]);
+        assert_eq!(report.skipped(), 250);
+        assert_eq!(report.extracted(), 50);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn should_commit_processed_records() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("checkpoint");
+        let mut loader = FailingLoader { remaining: 100 };
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| Engine::builder().with_checkpoint(&path).build()
+            .run(&mut VecExtractor::of(2 * BATCH_SIZE as u64), &SlowTransformer {}, &CollectingReporter::new(), &mut loader)));
+
+        assert!(result.is_err());
+        assert_eq!(Checkpoint::open(&path).unwrap().last_id(), Some(BATCH_SIZE as u64));
+    }
+
+    #[test]
+    fn should_flush_loader_before_checkpointing() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("checkpoint");
+        let mut loader = CollectingLoader::new();
+
+        Engine::builder().with_checkpoint(&path).build()
+            .run(&mut VecExtractor::of(200), &SlowTransformer {}, &CollectingReporter::new(), &mut loader).unwrap();
+
+        assert_eq!(loader.flushed, vec![64, 128, 192]);
+    }
+
+    #[test]
+    fn should_merge_consecutive_orders() {
+        let mut loader = CollectingLoader::new();
+
+        let report = Engine::builder().with_workers(2).with_aggregation(Aggregation::Consecutive).build()
+            .run(&mut VecExtractor::of(9), &GroupingTransformer { group: |id| id.div_ceil(3) }, &CollectingReporter::new(), &mut loader)
+            .unwrap();
+
+        assert_eq!(loader.ids, vec![1, 2, 3]);
+        assert_eq!(loader.line_counts, vec![3, 3, 3]);
+        assert_eq!(loader.lines, vec![vec![2, 3, 4], vec![5, 6, 7], vec![8, 9, 10]]);
+        assert_eq!(report.transformed(), 9);
+        assert_eq!(report.loaded(), 3);
+    }
+
+    #[test]
+    fn should_merge_orders_by_id() {
+        let mut loader = CollectingLoader::new();
+
+        Engine::builder().with_aggregation(Aggregation::Keyed).build()
+            .run(&mut VecExtractor::of(8), &GroupingTransformer { group: |id| id % 3 + 1 }, &CollectingReporter::new(), &mut loader)
+            .unwrap();
+
+        assert_eq!(loader.ids, vec![2, 3, 1]);
+        assert_eq!(loader.line_counts, vec![3, 3, 2]);
+        assert_eq!(loader.lines, vec![vec![2, 5, 8], vec![3, 6, 9], vec![4, 7]]);
+    }
+
+    #[test]
+    fn should_commit_aggregated_records_once_loaded() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("checkpoint");
+        let mut loader = FailingLoader { remaining: 23 };
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| Engine::builder().with_checkpoint(&path).with_aggregation(Aggregation::Consecutive).build()
+            .run(&mut VecExtractor::of(90), &GroupingTransformer { group: |id| id.div_ceil(3) }, &CollectingReporter::new(), &mut loader)));
+
+        assert!(result.is_err());
+        // the first complete order after BATCH_SIZE records ends with record 66
+        assert_eq!(Checkpoint::open(&path).unwrap().last_id(), Some(66));
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_propagate_transformer_panics() {
+        Engine::builder().with_workers(2).build()
+            .run(&mut VecExtractor::of(10), &PanickingTransformer {}, &CollectingReporter::new(), &mut CollectingLoader::new()).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn engine_builder_requires_workers_greater_than_0() {
+        Engine::builder().with_workers(0);
+    }
+
+    struct VecExtractor {
+        records: std::vec::IntoIter<Result<MapRecord, ExtractorError>>,
+    }
+
+    impl VecExtractor {
+        fn of(count: u64) -> Self {
+            VecExtractor::failing(count, |_| false)
+        }
+
+        /// Fails to extract the records whose id matches the given predicate.
+        fn failing(count: u64, fails: fn(u64) -> bool) -> Self {
+            let records: Vec<_> = (1..=count)
+                .map(|id| if fails(id) {
+                    Err(ExtractorError::at(id, "unreadable record"))
+                } else {
+                    Ok(MapRecord::new(id, vec![].into_iter().collect()).with_provenance(Provenance::default().with_line(id + 1)))
+                })
+                .collect();
+            VecExtractor { records: records.into_iter() }
+        }
+    }
+
+    impl Extractor<MapRecord> for VecExtractor {}
+
+    impl Iterator for VecExtractor {
+        type Item = Result<MapRecord, ExtractorError>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.records.next()
+        }
+    }
+
+    struct SlowTransformer {}
+
+    impl Transformer<MapRecord> for SlowTransformer {
+        fn transform(&self, record: MapRecord) -> Result<Order, DiscardedRecord> {
+            thread::sleep(Duration::from_micros(record.id() % 7 * 50));
+            if record.id().is_multiple_of(10) {
+                return Err(DiscardedRecord::new(&record, RecordErrorKind::Invalid, "Invalid record.".to_string()));
+            }
+            Ok(Order::builder()
+                .with_id(record.id())
+                .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+                .with_product_id("123456789".to_string())
+                .with_product_name("Nuts".to_string())
+                .with_quantity(Quantity::builder().with_quantity(Decimal::new(1, 0)).build())
+                .build())
+        }
+    }
+
+    /// Turns each record into a line of the order its id is mapped to.
+    struct GroupingTransformer {
+        group: fn(u64) -> u64,
+    }
+
+    impl Transformer<MapRecord> for GroupingTransformer {
+        fn transform(&self, record: MapRecord) -> Result<Order, DiscardedRecord> {
+            Ok(Order::builder()
+                .with_id((self.group)(record.id()))
+                .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+                .with_product_id(record.id().to_string())
+                .with_product_name("Nuts".to_string())
+                .with_quantity(Quantity::builder().with_quantity(Decimal::new(1, 0)).build())
+                .build())
+        }
+    }
+
+    struct PanickingTransformer {}
+
+    impl Transformer<MapRecord> for PanickingTransformer {
+        fn transform(&self, _record: MapRecord) -> Result<Order, DiscardedRecord> {
+            panic!("Transformer failure!");
+        }
+    }
+
+    struct CollectingReporter {
+        record_ids: RefCell<Vec<u64>>,
+        extraction_positions: RefCell<Vec<u64>>,
+    }
+
+    impl CollectingReporter {
+        fn new() -> Self {
+            CollectingReporter { record_ids: RefCell::new(vec![]), extraction_positions: RefCell::new(vec![]) }
+        }
+    }
+
+    impl Reporter for CollectingReporter {
+        fn report_record(&self, discarded_record: DiscardedRecord) {
+            self.record_ids.borrow_mut().push(discarded_record.id());
+        }
+
+        fn report_order(&self, _discarded_order: DiscardedOrder) {
+            panic!("Order discarded - should never happen!");
+        }
+
+        fn report_extraction(&self, extractor_error: ExtractorError) {
+            self.extraction_positions.borrow_mut().push(extractor_error.position());
+        }
+    }
+
+    struct CollectingLoader {
+        ids: Vec<u64>,
+        line_counts: Vec<usize>,
+        /// Lines of the records of each order.
+        lines: Vec<Vec<u64>>,
+        /// Id of the last order loaded at each flush.
+        flushed: Vec<u64>,
+        finished: bool,
+        aborted: bool,
+    }
+
+    impl CollectingLoader {
+        fn new() -> Self {
+            CollectingLoader { ids: vec![], line_counts: vec![], lines: vec![], flushed: vec![], finished: false, aborted: false }
+        }
+    }
+
+    impl Loader for CollectingLoader {
+        fn load(&mut self, order: Order) -> Result<(), DiscardedOrder> {
+            self.ids.push(order.id());
+            self.line_counts.push(order.line_items().len());
+            self.lines.push(order.provenances().iter().filter_map(Provenance::line).collect());
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+            self.flushed.extend(self.ids.last());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+            self.finished = true;
+            Ok(())
+        }
+
+        fn abort(&mut self) {
+            self.aborted = true;
+        }
+    }
+
+    struct FailingLoader {
+        remaining: u64,
+    }
+
+    struct UnfinishableLoader {
+        aborted: bool,
+    }
+
+    impl Loader for UnfinishableLoader {
+        fn load(&mut self, _order: Order) -> Result<(), DiscardedOrder> {
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+            Err("disk full".into())
+        }
+
+        fn abort(&mut self) {
+            self.aborted = true;
+        }
+    }
+
+    impl Loader for FailingLoader {
+        fn load(&mut self, _order: Order) -> Result<(), DiscardedOrder> {
+            assert!(self.remaining > 0, "Loader crashed!");
+            self.remaining -= 1;
+            Ok(())
+        }
     }
 }
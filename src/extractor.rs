@@ -1,29 +1,99 @@
 use core::fmt;
 use std::error::Error;
 
-use crate::record::Record;
+use crate::record::{Provenance, Record};
 
-pub trait Extractor<R>: Iterator<Item=R> where R: Record {}
+pub trait Extractor<R>: Iterator<Item=Result<R, ExtractorError>> where R: Record {}
 
+/// Failure to extract a record, discarded on its own since there is no record to transform.
 #[derive(Debug)]
 pub struct ExtractorError {
-    message: String
+    position: u64,
+    message: String,
+    category: Option<String>,
+    provenance: Box<Provenance>,
+    source: Option<Box<dyn Error + Send + Sync>>,
 }
 
 impl ExtractorError {
     pub fn new(message: &str) -> ExtractorError {
-        ExtractorError { message: message.to_string() }
+        ExtractorError::at(0, message)
+    }
+
+    /// Error extracting the record at the given position, which is then used as the id of the discarded extraction.
+    pub fn at(position: u64, message: &str) -> ExtractorError {
+        ExtractorError { position, message: message.to_string(), category: None, provenance: Box::default(), source: None }
+    }
+
+    /// Error extracting the record at the given position, caused by the given parse error.
+    pub fn caused_by<E: Error + Send + Sync + 'static>(position: u64, source: E) -> ExtractorError {
+        let message = source.to_string();
+        ExtractorError { position, message, category: None, provenance: Box::default(), source: Some(Box::new(source)) }
+    }
+
+    /// Sets the category of the failure, for messages telling where it occurred (e.g. `malformed JSON` for `expected
+    /// value at line 1 column 5`).
+    pub fn with_category(mut self, category: &str) -> ExtractorError {
+        self.category = Some(category.to_string());
+        self
+    }
+
+    /// Sets where the failure occurred in the source, as a line number (from 1) and a byte offset (from 0).
+    pub fn with_location(mut self, line: u64, byte: u64) -> ExtractorError {
+        self.provenance = Box::new(self.provenance.with_line(line).with_byte(byte));
+        self
+    }
+
+    /// Sets where the failure occurred in the source; the name of the source, if any, then prefixes the message
+    /// when displayed.
+    pub fn with_provenance(mut self, provenance: Provenance) -> ExtractorError {
+        self.provenance = Box::new(provenance);
+        self
+    }
+
+    pub(crate) fn renumbered(mut self, position: u64) -> ExtractorError {
+        self.position = position;
+        self
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn line(&self) -> Option<u64> {
+        self.provenance.line()
+    }
+
+    pub fn byte(&self) -> Option<u64> {
+        self.provenance.byte()
+    }
+
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// What went wrong, without telling where, so that the failures of a run can be counted together; the message
+    /// when no category was set.
+    pub fn category(&self) -> &str {
+        self.category.as_deref().unwrap_or(&self.message)
     }
 }
 
 impl fmt::Display for ExtractorError {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        write!(formatter, "{}", self.message)
+        match self.provenance.source() {
+            Some(source) => write!(formatter, "{}: {}", source, self.message),
+            None => write!(formatter, "{}", self.message),
+        }
     }
 }
 
 impl Error for ExtractorError {
-    fn description(&self) -> &str {
-        &self.message
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_ref().map(|source| source.as_ref() as &(dyn Error + 'static))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,219 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::vec;
+
+use crate::compression::Compression;
+use crate::extractor::{Extractor, ExtractorError};
+use crate::record::{MapRecord, Provenance, Record};
+
+/// Extracts the records of several files one file after the other, each with an extractor opened by the given
+/// function. Records are numbered across files, so that their ids stay unique and keep growing as long as the same
+/// files are extracted; each record keeps the path of its file as the source of its provenance.
+///
+/// Ids are positions across all the files, so a checkpoint of a run is only valid as long as no file is added,
+/// removed or changed before the ones already extracted: resuming after such a change would skip the wrong records.
+///
+/// A file which cannot be opened is discarded as a single failed extraction, and the extraction goes on with the
+/// next one.
+pub struct FilesExtractor<E, F> {
+    paths: vec::IntoIter<PathBuf>,
+    open: F,
+    current: Option<(String, E)>,
+    position: u64,
+}
+
+impl<E, F> FilesExtractor<E, F>
+    where E: Extractor<MapRecord>,
+          F: FnMut(File) -> Result<E, Box<dyn Error>> {
+    pub fn new(paths: Vec<PathBuf>, open: F) -> Self {
+        FilesExtractor { paths: paths.into_iter(), open, current: None, position: 0 }
+    }
+
+    /// Extracts the files matching a glob pattern (e.g. `stores/*.csv`), in path order.
+    pub fn glob(pattern: &str, open: F) -> Result<Self, Box<dyn Error>> {
+        let mut paths = Vec::new();
+        for path in glob::glob(pattern)? {
+            let path = path?;
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            return Err(format!("no file matches {}", pattern).into());
+        }
+        Ok(FilesExtractor::new(paths, open))
+    }
+
+    /// Extracts the files of a directory having one of the given extensions, whatever their case and compression, in
+    /// path order; subdirectories and other files are ignored.
+    pub fn directory<P: AsRef<Path>>(directory: P, extensions: &[&str], open: F) -> Result<Self, Box<dyn Error>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.is_file() && has_extension(&path, extensions) {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            return Err(format!("no {} file in {}", extensions.join(" or "), directory.as_ref().display()).into());
+        }
+        paths.sort();
+        Ok(FilesExtractor::new(paths, open))
+    }
+}
+
+/// Tells whether a path has one of the extensions, before any compression extension (e.g. `orders.csv.gz`).
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    let path = match Compression::of(path) {
+        Compression::None => path,
+        _ => Path::new(path.file_stem().unwrap_or_default()),
+    };
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.iter().any(|expected| extension.eq_ignore_ascii_case(expected)))
+}
+
+impl<E, F> Extractor<MapRecord> for FilesExtractor<E, F>
+    where E: Extractor<MapRecord>,
+          F: FnMut(File) -> Result<E, Box<dyn Error>> {}
+
+impl<E, F> Iterator for FilesExtractor<E, F>
+    where E: Extractor<MapRecord>,
+          F: FnMut(File) -> Result<E, Box<dyn Error>> {
+    type Item = Result<MapRecord, ExtractorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((source, extractor)) = &mut self.current {
+                match extractor.next() {
+                    Some(record) => {
+                        self.position += 1;
+                        return Some(match record {
+                            Ok(record) => {
+                                let provenance = record.provenance().clone().with_source(source);
+                                Ok(record.renumbered(self.position).with_provenance(provenance))
+                            }
+                            Err(e) => {
+                                let provenance = e.provenance().clone().with_source(source);
+                                Err(e.renumbered(self.position).with_provenance(provenance))
+                            }
+                        });
+                    }
+                    None => self.current = None,
+                }
+            }
+
+            let path = self.paths.next()?;
+            let source = path.display().to_string();
+            let open = &mut self.open;
+            match File::open(&path).map_err(Box::from).and_then(open) {
+                Ok(extractor) => self.current = Some((source, extractor)),
+                Err(e) => {
+                    self.position += 1;
+                    return Some(Err(ExtractorError::at(self.position, &e.to_string())
+                        .with_provenance(Provenance::default().with_source(&source))));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use tempfile::tempdir;
+
+    use crate::csv::dialect::Dialect;
+    use crate::csv::extractor::CsvExtractor;
+
+    use super::*;
+
+    #[test]
+    fn should_extract_files_matching_glob() {
+        let directory = tempdir().unwrap();
+        fs::write(directory.path().join("b.csv"), "Product Name,Year\nJam,2020\n").unwrap();
+        fs::write(directory.path().join("a.csv"), "Year,Product Name\n2019,Nuts\n2019,Salt\n").unwrap();
+        fs::write(directory.path().join("notes.txt"), "Not a source").unwrap();
+        let pattern = directory.path().join("*.csv");
+
+        let extracted_records: Vec<MapRecord> = FilesExtractor::glob(pattern.to_str().unwrap(), CsvExtractor::from).unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        let a = directory.path().join("a.csv").display().to_string();
+        let b = directory.path().join("b.csv").display().to_string();
+        assert_eq!(extracted_records.iter().map(Record::id).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(extracted_records.iter().map(|record| record.value_for("Product Name").unwrap().as_str()).collect::<Vec<_>>(), vec!["Nuts", "Salt", "Jam"]);
+        assert_eq!(extracted_records.iter().map(|record| record.value_for("Year").unwrap().as_str()).collect::<Vec<_>>(), vec!["2019", "2019", "2020"]);
+        assert_eq!(extracted_records.iter().map(|record| (record.provenance().source().unwrap(), record.provenance().line().unwrap())).collect::<Vec<_>>(),
+                   vec![(a.as_str(), 2), (a.as_str(), 3), (b.as_str(), 2)]);
+    }
+
+    #[test]
+    fn should_extract_files_of_directory() {
+        let directory = tempdir().unwrap();
+        fs::write(directory.path().join("2020.csv"), "Year\n2020\n").unwrap();
+        fs::write(directory.path().join("2019.csv"), "Year\n2019\n").unwrap();
+        fs::write(directory.path().join("2021.CSV"), "Year\n2021\n").unwrap();
+        let mut compressed = GzEncoder::new(File::create(directory.path().join("2022.csv.gz")).unwrap(), flate2::Compression::default());
+        compressed.write_all(b"Year\n2022\n").unwrap();
+        compressed.finish().unwrap();
+        fs::write(directory.path().join("notes.txt"), "Not a source").unwrap();
+        fs::create_dir(directory.path().join("archive.csv")).unwrap();
+
+        let extracted_records: Vec<MapRecord> = FilesExtractor::directory(directory.path(), &["csv"], CsvExtractor::from).unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(extracted_records.iter().map(|record| record.value_for("Year").unwrap().as_str()).collect::<Vec<_>>(), vec!["2019", "2020", "2021", "2022"]);
+    }
+
+    #[test]
+    fn should_number_failed_extractions_across_files() {
+        let directory = tempdir().unwrap();
+        fs::write(directory.path().join("a.csv"), "Year\n2019\n").unwrap();
+        fs::write(directory.path().join("b.csv"), "Year,Product Name\n2020\n").unwrap();
+        fs::write(directory.path().join("c.csv"), "").unwrap();
+        let dialect = Dialect::default();
+
+        let mut extracted_records: Vec<Result<MapRecord, ExtractorError>> = FilesExtractor::directory(directory.path(), &["csv"], |file| {
+            match file.metadata()?.len() {
+                0 => Err("empty file".into()),
+                _ => CsvExtractor::with_dialect(file, &dialect),
+            }
+        }).unwrap().collect();
+
+        assert_eq!(extracted_records.remove(0).unwrap().id(), 1);
+        let malformed = extracted_records.remove(0).err().unwrap();
+        assert_eq!(malformed.position(), 2);
+        assert_eq!(malformed.line(), Some(2));
+        assert_eq!(malformed.provenance().source(), Some(directory.path().join("b.csv").display().to_string().as_str()));
+        let unopened = extracted_records.remove(0).err().unwrap();
+        assert_eq!(unopened.position(), 3);
+        assert_eq!(unopened.to_string(), format!("{}: empty file", directory.path().join("c.csv").display()));
+        assert!(extracted_records.is_empty());
+    }
+
+    #[test]
+    fn should_fail_when_no_file_matches() {
+        let directory = tempdir().unwrap();
+        let pattern = directory.path().join("*.csv").display().to_string();
+
+        let error = FilesExtractor::glob(&pattern, CsvExtractor::from).err().unwrap();
+
+        assert_eq!(error.to_string(), format!("no file matches {}", pattern));
+    }
+
+    #[test]
+    fn should_fail_when_directory_has_no_file_of_extensions() {
+        let directory = tempdir().unwrap();
+        fs::write(directory.path().join("notes.txt"), "Not a source").unwrap();
+
+        let error = FilesExtractor::directory(directory.path(), &["json", "ndjson"], CsvExtractor::from).err().unwrap();
+
+        assert_eq!(error.to_string(), format!("no json or ndjson file in {}", directory.path().display()));
+    }
+}
@@ -0,0 +1,416 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Stdin};
+use std::path::Path;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde_json::{Deserializer, Number, Value as Json};
+use serde_json::error::Category;
+
+use crate::compression::Decompressed;
+use crate::encoding::{self, Decoded, Encoding};
+use crate::extractor::{Extractor, ExtractorError};
+use crate::record::{MapRecord, Provenance};
+use crate::value::Value;
+
+/// Extracts records from either a JSON array of objects or newline-delimited JSON (one object per line), told apart
+/// by the first character of the file. Nested objects and arrays are flattened into dotted keys (`product.id`,
+/// `items.0.name`). Values keep their JSON types, and `null`s have no text. Each record has the line, byte offset and
+/// text of its object as provenance.
+pub struct JsonExtractor<R: Read = File> {
+    reader: Tracking<BufReader<Decoded<Decompressed<R>>>>,
+    format: Format,
+    position: u64,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Format {
+    Array { first: bool },
+    Lines,
+    Done,
+}
+
+impl JsonExtractor<File> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        JsonExtractor::from(File::open(path)?)
+    }
+}
+
+impl JsonExtractor<Stdin> {
+    pub fn stdin() -> Result<Self, Box<dyn Error>> {
+        JsonExtractor::from(io::stdin())
+    }
+}
+
+impl<R: Read> JsonExtractor<R> {
+    pub fn from(reader: R) -> Result<Self, Box<dyn Error>> {
+        JsonExtractor::with_encoding(reader, Encoding::default())
+    }
+
+    /// Extracts records from an input in the given encoding, transcoded to UTF-8 and decompressed when compressed.
+    pub fn with_encoding(reader: R, encoding: Encoding) -> Result<Self, Box<dyn Error>> {
+        let mut reader = Tracking::new(BufReader::new(encoding::decode(Decompressed::new(reader)?, encoding)?));
+        let format = match skip_whitespace(&mut reader)? {
+            Some(b'[') => {
+                reader.consume(1);
+                Format::Array { first: true }
+            }
+            _ => Format::Lines,
+        };
+        Ok(JsonExtractor { reader, format, position: 0 })
+    }
+
+    /// Elements of an array are parsed one at a time straight from the reader. A malformed array cannot be
+    /// resynchronised, so the extraction stops at the first error.
+    fn next_element(&mut self, first: bool) -> Option<Result<MapRecord, ExtractorError>> {
+        let (element, provenance) = match self.start_element(first) {
+            Ok(true) => {
+                let provenance = self.reader.provenance();
+                self.reader.record();
+                let element = Deserializer::from_reader(&mut self.reader).into_iter::<Json>().next()
+                    .unwrap_or(Ok(Json::Null))
+                    .map_err(json_error);
+                (element, provenance.with_raw_text(&self.reader.take_recorded()))
+            }
+            Ok(false) => {
+                self.format = Format::Done;
+                return None;
+            }
+            Err(e) => (Err(e), self.reader.provenance()),
+        };
+        self.position += 1;
+        match element.and_then(to_columns) {
+            Ok(columns) => {
+                self.format = Format::Array { first: false };
+                Some(Ok(MapRecord::with_typed_columns(self.position, columns).with_provenance(provenance)))
+            }
+            Err(e) => {
+                self.format = Format::Done;
+                Some(Err(e.renumbered(self.position).with_provenance(provenance)))
+            }
+        }
+    }
+
+    /// Moves past the separator preceding the next element, telling whether there is one.
+    fn start_element(&mut self, first: bool) -> Result<bool, ExtractorError> {
+        match skip_whitespace(&mut self.reader).map_err(io_error)? {
+            Some(b']') => return Ok(false),
+            Some(b',') if !first => self.reader.consume(1),
+            Some(_) if first => return Ok(true),
+            Some(byte) => return Err(malformed_array(&format!("expected `,` or `]`, found `{}`", byte as char))),
+            None => return Err(malformed_array("unexpected end of array")),
+        }
+        match skip_whitespace(&mut self.reader).map_err(io_error)? {
+            Some(_) => Ok(true),
+            None => Err(malformed_array("unexpected end of array")),
+        }
+    }
+
+    /// Lines are parsed independently, so a malformed line is reported and the extraction goes on.
+    fn next_line(&mut self) -> Option<Result<MapRecord, ExtractorError>> {
+        let mut line = String::new();
+        let mut provenance;
+        loop {
+            line.clear();
+            provenance = self.reader.provenance();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) if line.trim().is_empty() => continue,
+                Ok(_) => break,
+                Err(e) => {
+                    self.format = Format::Done;
+                    self.position += 1;
+                    return Some(Err(io_error(e).renumbered(self.position)));
+                }
+            }
+        }
+        self.position += 1;
+        let provenance = provenance.with_raw_text(line.trim_end_matches(['\r', '\n']));
+        let columns = serde_json::from_str::<Json>(&line)
+            .map_err(json_error)
+            .and_then(to_columns);
+        Some(match columns {
+            Ok(columns) => Ok(MapRecord::with_typed_columns(self.position, columns).with_provenance(provenance)),
+            Err(e) => Err(e.renumbered(self.position).with_provenance(provenance)),
+        })
+    }
+}
+
+impl<R: Read> Extractor<MapRecord> for JsonExtractor<R> {}
+
+impl<R: Read> Iterator for JsonExtractor<R> {
+    type Item = Result<MapRecord, ExtractorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.format {
+            Format::Array { first } => self.next_element(first),
+            Format::Lines => self.next_line(),
+            Format::Done => None,
+        }
+    }
+}
+
+/// Buffered reader counting the lines and bytes consumed, and recording them on demand, so that the provenance of a
+/// record is known once it is parsed.
+struct Tracking<R: BufRead> {
+    reader: R,
+    consumed: Consumed,
+}
+
+#[derive(Default)]
+struct Consumed {
+    /// Line terminators consumed so far.
+    lines: u64,
+    bytes: u64,
+    recorded: Option<Vec<u8>>,
+}
+
+impl<R: BufRead> Tracking<R> {
+    fn new(reader: R) -> Self {
+        Tracking { reader, consumed: Consumed::default() }
+    }
+
+    /// Provenance of a record starting at the next byte.
+    fn provenance(&self) -> Provenance {
+        Provenance::default().with_line(self.consumed.lines + 1).with_byte(self.consumed.bytes)
+    }
+
+    /// Records the bytes consumed from now on, until taken.
+    fn record(&mut self) {
+        self.consumed.recorded = Some(Vec::new());
+    }
+
+    fn take_recorded(&mut self) -> String {
+        let recorded = self.consumed.recorded.take().unwrap_or_default();
+        String::from_utf8_lossy(&recorded).into_owned()
+    }
+}
+
+impl Consumed {
+    fn track(&mut self, bytes: &[u8]) {
+        self.lines += bytes.iter().filter(|&&byte| byte == b'\n').count() as u64;
+        self.bytes += bytes.len() as u64;
+        if let Some(recorded) = &mut self.recorded {
+            recorded.extend_from_slice(bytes);
+        }
+    }
+}
+
+impl<R: BufRead> Read for Tracking<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buffer)?;
+        self.consumed.track(&buffer[..read]);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for Tracking<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        // the bytes consumed are still buffered, so filling the buffer reads nothing further
+        if let Ok(buffer) = self.reader.fill_buf() {
+            self.consumed.track(&buffer[..amount.min(buffer.len())]);
+        }
+        self.reader.consume(amount);
+    }
+}
+
+/// Peeks at the first non-whitespace byte, leaving it in the reader.
+fn skip_whitespace<R: BufRead>(reader: &mut R) -> std::io::Result<Option<u8>> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(None);
+        }
+        match buffer.iter().position(|byte| !byte.is_ascii_whitespace()) {
+            Some(index) => {
+                let byte = buffer[index];
+                reader.consume(index);
+                return Ok(Some(byte));
+            }
+            None => {
+                let length = buffer.len();
+                reader.consume(length);
+            }
+        }
+    }
+}
+
+fn json_error(error: serde_json::Error) -> ExtractorError {
+    let category = match error.classify() {
+        Category::Io => "I/O error",
+        Category::Syntax => "malformed JSON",
+        Category::Data => "invalid JSON value",
+        Category::Eof => "unexpected end of JSON",
+    };
+    ExtractorError::caused_by(0, error).with_category(category)
+}
+
+fn io_error(error: io::Error) -> ExtractorError {
+    ExtractorError::caused_by(0, error).with_category("I/O error")
+}
+
+fn malformed_array(message: &str) -> ExtractorError {
+    ExtractorError::new(message).with_category("malformed JSON array")
+}
+
+/// Columns of an object, ordered by name.
+fn to_columns(value: Json) -> Result<Vec<(String, Value)>, ExtractorError> {
+    match value {
+        Json::Object(_) => {
+            let mut columns = Vec::new();
+            flatten(String::new(), value, &mut columns);
+            columns.sort_by(|(name, _), (other_name, _)| name.cmp(other_name));
+            Ok(columns)
+        }
+        _ => Err(ExtractorError::new(&format!("expected an object, found `{}`", value)).with_category("not an object")),
+    }
+}
+
+fn flatten(key: String, value: Json, columns: &mut Vec<(String, Value)>) {
+    let prefix = |name: &str| if key.is_empty() { name.to_string() } else { format!("{}.{}", key, name) };
+    match value {
+        Json::Object(object) => object.into_iter()
+            .for_each(|(name, value)| flatten(prefix(&name), value, columns)),
+        Json::Array(array) => array.into_iter().enumerate()
+            .for_each(|(index, value)| flatten(prefix(&index.to_string()), value, columns)),
+        Json::Null => columns.push((key, Value::Null)),
+        Json::Bool(value) => columns.push((key, Value::Bool(value))),
+        Json::Number(value) => columns.push((key, to_value(value))),
+        Json::String(value) => columns.push((key, Value::String(value))),
+    }
+}
+
+/// Integers fitting 64 bits, decimals otherwise; numbers beyond the range of decimals are kept as strings.
+fn to_value(number: Number) -> Value {
+    if let Some(value) = number.as_i64() {
+        return Value::Int(value);
+    }
+    let text = number.to_string();
+    Decimal::from_str(&text)
+        .or_else(|_| Decimal::from_scientific(&text))
+        .map_or(Value::String(text), Value::Decimal)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Seek, SeekFrom, Write};
+
+    use tempfile::tempfile;
+
+    use crate::record::Record;
+
+    use super::*;
+
+    #[test]
+    fn should_extract_from_array() {
+        let extracted_records = extract(" [\n\
+                      {\"Column\": \"Value 1\", \"Another Column\": 12.20},\n\
+                      {\"Column\": \"Value 2\", \"Another Column\": null}\n\
+                      ]");
+
+        let first_expected_record = MapRecord::with_typed_columns(1, vec![
+            ("Another Column".to_string(), Value::Decimal(Decimal::new(1220, 2))),
+            ("Column".to_string(), Value::from("Value 1")),
+        ]).with_provenance(at(2, 3, "{\"Column\": \"Value 1\", \"Another Column\": 12.20}"));
+        let second_expected_record = MapRecord::with_typed_columns(2, vec![
+            ("Another Column".to_string(), Value::Null),
+            ("Column".to_string(), Value::from("Value 2")),
+        ]).with_provenance(at(3, 51, "{\"Column\": \"Value 2\", \"Another Column\": null}"));
+
+        assert_eq!(extracted_records.len(), 2);
+        assert_eq!(extracted_records[0].as_ref().unwrap(), &first_expected_record);
+        assert_eq!(extracted_records[1].as_ref().unwrap(), &second_expected_record);
+        assert_eq!(extracted_records[0].as_ref().unwrap().value_for("Another Column"), Some(&"12.20".to_string()));
+        assert_eq!(extracted_records[1].as_ref().unwrap().value_for("Another Column"), None);
+    }
+
+    #[test]
+    fn should_keep_numbers_beyond_decimals_as_strings() {
+        let extracted_records = extract("{\"Column\": 1e40}");
+
+        assert_eq!(extracted_records[0].as_ref().unwrap().typed_value_for("Column").as_deref(), Some(&Value::from("1e+40")));
+    }
+
+    #[test]
+    fn should_extract_from_empty_array() {
+        assert!(extract("[ ]").is_empty());
+    }
+
+    #[test]
+    fn should_stop_at_malformed_array() {
+        let extracted_records = extract("[{\"Column\": \"Value 1\"} {\"Column\": \"Value 2\"}]");
+
+        assert_eq!(extracted_records.len(), 2);
+        assert!(extracted_records[0].is_ok());
+        assert_eq!(extracted_records[1].as_ref().err().unwrap().position(), 2);
+        assert_eq!(extracted_records[1].as_ref().err().unwrap().provenance(), &Provenance::default().with_line(1).with_byte(23));
+    }
+
+    #[test]
+    fn should_extract_from_lines() {
+        let extracted_records = extract("{\"Column\": \"Value 1\", \"Nested\": {\"Column\": true, \"Array\": [1, 2]}}\n\
+                      \n\
+                      {\"Column\": \"Value 2\"}\n");
+
+        let first_expected_record = MapRecord::with_typed_columns(1, vec![
+            ("Column".to_string(), Value::from("Value 1")),
+            ("Nested.Array.0".to_string(), Value::Int(1)),
+            ("Nested.Array.1".to_string(), Value::Int(2)),
+            ("Nested.Column".to_string(), Value::Bool(true)),
+        ]).with_provenance(at(1, 0, "{\"Column\": \"Value 1\", \"Nested\": {\"Column\": true, \"Array\": [1, 2]}}"));
+        let second_expected_record = MapRecord::with_typed_columns(2, vec![
+            ("Column".to_string(), Value::from("Value 2")),
+        ]).with_provenance(at(3, 68, "{\"Column\": \"Value 2\"}"));
+
+        assert_eq!(extracted_records.len(), 2);
+        assert_eq!(extracted_records[0].as_ref().unwrap(), &first_expected_record);
+        assert_eq!(extracted_records[1].as_ref().unwrap(), &second_expected_record);
+    }
+
+    #[test]
+    fn should_report_malformed_lines_and_carry_on() {
+        let extracted_records = extract("{\"Column\": \"Value 1\"}\n\
+                      {\"Column\": \n\
+                      \"Value 3\"\n\
+                      {\"Column\": \"Value 4\"}\n");
+
+        assert_eq!(extracted_records.len(), 4);
+        assert!(extracted_records[0].is_ok());
+        assert_eq!(extracted_records[1].as_ref().err().unwrap().position(), 2);
+        assert_eq!(extracted_records[1].as_ref().err().unwrap().category(), "unexpected end of JSON");
+        assert_eq!(extracted_records[1].as_ref().err().unwrap().provenance(), &at(2, 22, "{\"Column\": "));
+        assert_eq!(extracted_records[2].as_ref().err().unwrap().to_string(), "expected an object, found `\"Value 3\"`");
+        assert_eq!(extracted_records[2].as_ref().err().unwrap().category(), "not an object");
+        assert_eq!(extracted_records[3].as_ref().unwrap().id(), 4);
+    }
+
+    #[test]
+    fn should_extract_in_encoding() {
+        let mut file = tempfile().unwrap();
+        file.write_all(&[0xff, 0xfe]).unwrap();
+        file.write_all(&"{\"Column\": \"Crème\"}\n".encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<_>>()).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+
+        let extracted_records: Vec<MapRecord> = JsonExtractor::with_encoding(file, Encoding::Detect).unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(extracted_records[0].value_for("Column"), Some(&"Crème".to_string()));
+    }
+
+    fn at(line: u64, byte: u64, raw_text: &str) -> Provenance {
+        Provenance::default().with_line(line).with_byte(byte).with_raw_text(raw_text)
+    }
+
+    fn extract(content: &str) -> Vec<Result<MapRecord, ExtractorError>> {
+        let mut file = tempfile().unwrap();
+        write!(file, "{}", content).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        JsonExtractor::from(file).unwrap().collect()
+    }
+}
@@ -0,0 +1,297 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json::Number;
+
+use crate::compression::{Compressed, Compression};
+use crate::loader::{DiscardedOrder, Loader, OrderErrorKind};
+use crate::order::Order;
+use crate::staging::Staging;
+
+/// Layout of the loaded orders: a single JSON array, or newline-delimited JSON with one order per line. Line items
+/// are nested in a `line_items` array of their order, with their price only when they have one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Array,
+    Lines,
+}
+
+pub struct JsonLoader<W: Write> {
+    writer: BufWriter<Compressed<W>>,
+    format: Format,
+    empty: bool,
+    staging: Option<Staging>,
+}
+
+#[derive(Serialize)]
+struct JsonOrder<'a> {
+    id: u64,
+    date: String,
+    line_items: Vec<JsonLineItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonLineItem<'a> {
+    product_id: &'a str,
+    product_name: &'a str,
+    quantity: Number,
+    unit: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit_price: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<Number>,
+}
+
+impl JsonLoader<File> {
+    /// Creates the file at the given path once finished, replacing any existing one; an aborted run leaves the path
+    /// untouched.
+    pub fn create<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Box<dyn Error>> {
+        let (file, staging) = Staging::create(path)?;
+        Ok(JsonLoader::to(file, format)?.staged(staging))
+    }
+}
+
+impl<W: Write> JsonLoader<W> {
+    pub fn to(writer: W, format: Format) -> Result<Self, Box<dyn Error>> {
+        JsonLoader::with_compression(writer, format, Compression::None)
+    }
+
+    /// Writes orders in the given compression; a compressed output is complete only once the loader is finished.
+    pub fn with_compression(writer: W, format: Format, compression: Compression) -> Result<Self, Box<dyn Error>> {
+        let mut writer = BufWriter::new(Compressed::new(writer, compression)?);
+        if format == Format::Array {
+            writer.write_all(b"[")?;
+        }
+        Ok(JsonLoader { writer, format, empty: true, staging: None })
+    }
+
+    /// Writes to the temporary file of the given staging, committed once finished and discarded when aborted.
+    pub fn staged(mut self, staging: Staging) -> Self {
+        self.staging = Some(staging);
+        self
+    }
+
+    /// Serialises an order along with its separator, to be written in one go so that a failing order leaves nothing
+    /// behind.
+    fn serialise(&self, order: &JsonOrder) -> Result<Vec<u8>, serde_json::Error> {
+        let mut serialised = match self.format {
+            Format::Array if self.empty => b"\n".to_vec(),
+            Format::Array => b",\n".to_vec(),
+            Format::Lines => Vec::new(),
+        };
+        serde_json::to_writer(&mut serialised, order)?;
+        if self.format == Format::Lines {
+            serialised.push(b'\n');
+        }
+        Ok(serialised)
+    }
+}
+
+fn encode(order: &Order) -> Result<JsonOrder<'_>, serde_json::Error> {
+    let line_items = order.line_items().iter()
+        .map(|line_item| Ok(JsonLineItem {
+            product_id: line_item.product_id(),
+            product_name: line_item.product_name(),
+            quantity: Number::from_str(&line_item.quantity().quantity().to_string())?,
+            unit: format!("{:?}", line_item.quantity().unit()),
+            unit_price: line_item.price().map(|price| Number::from_str(&price.unit_price().to_string())).transpose()?,
+            currency: line_item.price().map(|price| price.currency().code()),
+            total: line_item.price().map(|price| Number::from_str(&price.total().to_string())).transpose()?,
+        }))
+        .collect::<Result<_, serde_json::Error>>()?;
+    Ok(JsonOrder { id: order.id(), date: order.date().to_string(), line_items })
+}
+
+impl<W: Write> Loader for JsonLoader<W> {
+    fn load(&mut self, order: Order) -> Result<(), DiscardedOrder> {
+        let written = match encode(&order).and_then(|json_order| self.serialise(&json_order)) {
+            Ok(serialised) => self.writer.write_all(&serialised).map_err(|e| (OrderErrorKind::Io, e.to_string())),
+            Err(e) => Err((OrderErrorKind::Encoding, e.to_string())),
+        };
+        match written {
+            Ok(()) => {
+                self.empty = false;
+                Ok(())
+            }
+            Err((kind, message)) => Err(DiscardedOrder::new(order, kind, message)),
+        }
+    }
+
+    /// Writes what is still buffered; a compressed output only becomes durable once finished.
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Closes the array.
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.format == Format::Array {
+            self.writer.write_all(if self.empty { b"]\n" } else { b"\n]\n" })?;
+        }
+        self.writer.flush()?;
+        self.writer.get_mut().finish()?;
+        if let Some(staging) = self.staging.take() {
+            staging.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Removes a staged output; otherwise the array of an aborted run is left open, so that the output is not
+    /// mistaken for a complete one.
+    fn abort(&mut self) {
+        let _ = self.writer.flush();
+        if let Some(staging) = self.staging.take() {
+            staging.discard();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use chrono::NaiveDate;
+    use flate2::read::MultiGzDecoder;
+    use rust_decimal::Decimal;
+
+    use crate::order::Quantity;
+
+    use super::*;
+
+    #[test]
+    fn should_load_array() {
+        let mut loader = JsonLoader::to(vec![], Format::Array).unwrap();
+        loader.load(order(12, Decimal::new(1220, 2))).unwrap();
+        loader.load(order(13, Decimal::new(1, 0))).unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(String::from_utf8(written(loader)).unwrap(), "[\n\
+                    {\"id\":12,\"date\":\"2019-08-27\",\"line_items\":[{\"product_id\":\"123456789\",\"product_name\":\"Nuts\",\"quantity\":12.20,\"unit\":\"KG\"}]},\n\
+                    {\"id\":13,\"date\":\"2019-08-27\",\"line_items\":[{\"product_id\":\"123456789\",\"product_name\":\"Nuts\",\"quantity\":1,\"unit\":\"KG\"}]}\n\
+                    ]\n");
+    }
+
+    #[test]
+    fn should_load_empty_array() {
+        let mut loader = JsonLoader::to(vec![], Format::Array).unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(String::from_utf8(written(loader)).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn should_leave_array_of_aborted_run_open() {
+        let mut loader = JsonLoader::to(vec![], Format::Array).unwrap();
+        loader.load(order(12, Decimal::new(1220, 2))).unwrap();
+        loader.abort();
+
+        assert_eq!(String::from_utf8(written(loader)).unwrap(), "[\n\
+                    {\"id\":12,\"date\":\"2019-08-27\",\"line_items\":[{\"product_id\":\"123456789\",\"product_name\":\"Nuts\",\"quantity\":12.20,\"unit\":\"KG\"}]}");
+    }
+
+    #[test]
+    fn should_load_price() {
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name("Nuts".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(2, 0)).build())
+            .with_unit_price(Decimal::new(150, 2))
+            .with_currency("EUR".parse().unwrap())
+            .build();
+        let mut loader = JsonLoader::to(vec![], Format::Lines).unwrap();
+        loader.load(order).unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(String::from_utf8(written(loader)).unwrap(), "\
+                    {\"id\":12,\"date\":\"2019-08-27\",\"line_items\":[{\"product_id\":\"123456789\",\"product_name\":\"Nuts\",\"quantity\":2,\"unit\":\"KG\",\"unit_price\":1.50,\"currency\":\"EUR\",\"total\":3.00}]}\n");
+    }
+
+    #[test]
+    fn should_load_lines() {
+        let mut loader = JsonLoader::to(vec![], Format::Lines).unwrap();
+        loader.load(order(12, Decimal::new(1220, 2))).unwrap();
+        loader.load(order(13, Decimal::new(1, 0))).unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(String::from_utf8(written(loader)).unwrap(), "\
+                    {\"id\":12,\"date\":\"2019-08-27\",\"line_items\":[{\"product_id\":\"123456789\",\"product_name\":\"Nuts\",\"quantity\":12.20,\"unit\":\"KG\"}]}\n\
+                    {\"id\":13,\"date\":\"2019-08-27\",\"line_items\":[{\"product_id\":\"123456789\",\"product_name\":\"Nuts\",\"quantity\":1,\"unit\":\"KG\"}]}\n");
+    }
+
+    #[test]
+    fn should_buffer_orders_until_flushed() {
+        let mut loader = JsonLoader::to(FailingWriter {}, Format::Lines).unwrap();
+
+        loader.load(order(12, Decimal::new(1220, 2))).unwrap();
+
+        assert_eq!(loader.flush().unwrap_err().to_string(), "disk full");
+    }
+
+    #[test]
+    fn should_discard_order_when_write_fails() {
+        let mut loader = JsonLoader::to(FailingWriter {}, Format::Lines).unwrap();
+        let product_name = "Nuts".repeat(4096);
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name(product_name)
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
+            .build();
+
+        let discarded_order = loader.load(order).err().unwrap();
+
+        assert_eq!(discarded_order.order().id(), 12);
+        assert_eq!(discarded_order.kind(), OrderErrorKind::Io);
+        assert_eq!(discarded_order.error_message(), "disk full");
+    }
+
+    #[test]
+    fn should_load_compressed() {
+        let mut loader = JsonLoader::with_compression(vec![], Format::Lines, Compression::Gzip).unwrap();
+        loader.load(order(12, Decimal::new(1220, 2))).unwrap();
+        loader.finish().unwrap();
+
+        let mut loaded_content = String::new();
+        MultiGzDecoder::new(&written(loader)[..]).read_to_string(&mut loaded_content).unwrap();
+        assert_eq!(loaded_content, "{\"id\":12,\"date\":\"2019-08-27\",\"line_items\":[{\"product_id\":\"123456789\",\"product_name\":\"Nuts\",\"quantity\":12.20,\"unit\":\"KG\"}]}\n");
+    }
+
+    fn written(loader: JsonLoader<Vec<u8>>) -> Vec<u8> {
+        match loader.writer.into_inner().ok().unwrap() {
+            Compressed::None(writer) => writer,
+            Compressed::Gzip(writer) => writer.finish().unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn order(id: u64, quantity: Decimal) -> Order {
+        Order::builder()
+            .with_id(id)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_product_id("123456789".to_string())
+            .with_product_name("Nuts".to_string())
+            .with_quantity(Quantity::builder().with_quantity(quantity).build())
+            .build()
+    }
+
+    struct FailingWriter {}
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Err(std::io::Error::other("disk full"))
+        }
+    }
+}
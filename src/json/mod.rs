@@ -0,0 +1,2 @@
+pub mod extractor;
+pub mod loader;
\ No newline at end of file
@@ -1,15 +1,25 @@
 pub mod record;
+pub mod value;
 pub mod order;
+pub mod encoding;
+pub mod compression;
 
 pub mod extractor;
+pub mod files;
 
 pub mod transformer;
 pub mod traderjoes;
+pub mod mapping;
 
 pub mod loader;
+pub mod staging;
 
 pub mod reporter;
 
 pub mod engine;
+pub mod report;
+pub mod checkpoint;
 
-pub mod csv;
\ No newline at end of file
+pub mod csv;
+pub mod json;
+pub mod sqlite;
\ No newline at end of file
@@ -1,25 +1,84 @@
+use std::error::Error;
+
 use crate::order::Order;
+use crate::record::Provenance;
 
 pub trait Loader {
     fn load(&mut self, order: Order) -> Result<(), DiscardedOrder>;
+
+    /// Makes the orders loaded so far durable; called before the progress of the run is checkpointed.
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Orders loaded successfully but lost since, e.g. with a batch failing to commit; the engine collects them after
+    /// each call to the loader.
+    fn rolled_back(&mut self) -> Vec<DiscardedOrder> {
+        Vec::new()
+    }
+
+    /// Called once all the orders have been loaded.
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Called when the run is aborted; loaders able to do so should roll back or mark their output incomplete.
+    fn abort(&mut self) {}
 }
 
 #[derive(Debug)]
 pub struct DiscardedOrder {
-    order: Order,
+    order: Box<Order>,
+    kind: OrderErrorKind,
     error_message: String,
 }
 
 impl DiscardedOrder {
-    pub fn new(order: Order, error_message: String) -> DiscardedOrder {
-        DiscardedOrder { order, error_message }
+    pub fn new(order: Order, kind: OrderErrorKind, error_message: String) -> DiscardedOrder {
+        DiscardedOrder { order: Box::new(order), kind, error_message }
     }
 
     pub fn order(&self) -> &Order {
         &self.order
     }
 
+    /// Provenance of the records the order was transformed from.
+    pub fn provenances(&self) -> &[Provenance] {
+        self.order.provenances()
+    }
+
+    pub fn kind(&self) -> OrderErrorKind {
+        self.kind
+    }
+
     pub fn error_message(&self) -> &str {
         &self.error_message
     }
-}
\ No newline at end of file
+}
+
+/// Machine-readable reason an order was discarded.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum OrderErrorKind {
+    /// Writing to the output failed.
+    Io,
+    /// The order cannot be represented in the output format.
+    Encoding,
+    /// An order with the same id has already been loaded.
+    Duplicate,
+    /// The output refused the order, e.g. for breaking a database constraint.
+    Constraint,
+    /// The order cannot be merged with the other orders sharing its id.
+    Conflict,
+}
+
+impl OrderErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            OrderErrorKind::Io => "io",
+            OrderErrorKind::Encoding => "encoding",
+            OrderErrorKind::Duplicate => "duplicate",
+            OrderErrorKind::Constraint => "constraint",
+            OrderErrorKind::Conflict => "conflict",
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::{Parser, ValueEnum};
+use rusqlite::Connection;
+
+use poor_man_etl::compression::Compression;
+use poor_man_etl::csv::dialect::Dialect;
+use poor_man_etl::csv::extractor::CsvExtractor;
+use poor_man_etl::csv::loader::CsvLoader;
+use poor_man_etl::encoding::Encoding;
+use poor_man_etl::engine::{Aggregation, Engine, EngineError};
+use poor_man_etl::extractor::{Extractor, ExtractorError};
+use poor_man_etl::files::FilesExtractor;
+use poor_man_etl::json::extractor::JsonExtractor;
+use poor_man_etl::json::loader::{Format, JsonLoader};
+use poor_man_etl::loader::{DiscardedOrder, Loader};
+use poor_man_etl::mapping::transformer::MappingTransformer;
+use poor_man_etl::order::Unit;
+use poor_man_etl::record::{MapRecord, Provenance};
+use poor_man_etl::reporter::Reporter;
+use poor_man_etl::sqlite::extractor::{RecordId, SqlExtractor};
+use poor_man_etl::sqlite::loader::SqliteLoader;
+use poor_man_etl::staging::Staging;
+use poor_man_etl::traderjoes::transformer::TraderJoesTransformer;
+use poor_man_etl::transformer::{DiscardedRecord, NormalisingTransformer, Transformer};
+
+const FAILURE: i32 = 1;
+const DISCARDS: i32 = 3;
+
+/// Path standing for the standard input as a source, and for the standard output as an output.
+const STANDARD_STREAM: &str = "-";
+
+/// Characters telling a glob pattern from a path.
+const GLOB_CHARACTERS: &[char] = &['*', '?', '['];
+
+/// Extensions of the files extracted from a source directory, by format.
+const CSV_EXTENSIONS: &[&str] = &["csv"];
+const JSON_EXTENSIONS: &[&str] = &["json", "ndjson", "jsonl"];
+
+/// Extracts records from a source, transforms them into orders and loads them into an output.
+///
+/// Exits with 1 when the run fails, and with 3 when records failed to be extracted, or records or orders were
+/// discarded, and --fail-on-discard is given.
+#[derive(Parser)]
+#[command(name = "poor-man-etl", version)]
+struct Arguments {
+    /// Source file, or - for standard input. CSV and JSON sources may also be a directory, whose files with an
+    /// extension of their format are extracted one after the other, or a glob pattern (e.g. 'stores/*.csv')
+    #[arg(long)]
+    source: PathBuf,
+
+    /// Source format, guessed from the source extension when missing
+    #[arg(long, value_enum)]
+    source_format: Option<SourceFormat>,
+
+    /// Encoding of a CSV or JSON source, as a label (e.g. windows-1252, utf-16le) or detect; a byte order mark takes
+    /// precedence
+    #[arg(long, value_parser = str::parse::<Encoding>, default_value = "utf-8")]
+    encoding: Encoding,
+
+    /// Query extracting the records from a SQLite source
+    #[arg(long, required_if_eq("source_format", "sqlite"))]
+    query: Option<String>,
+
+    /// Built-in transformer name (traderjoes) or path to a TOML mapping file
+    #[arg(long)]
+    transformer: String,
+
+    /// Unit quantities are converted to (kg, g, lb, oz, l, ml or each), records in other dimensions being discarded
+    #[arg(long, value_parser = str::parse::<Unit>)]
+    unit: Option<Unit>,
+
+    /// Output file, or - for standard output
+    #[arg(long)]
+    output: PathBuf,
+
+    /// Output format, guessed from the output extension when missing
+    #[arg(long, value_enum)]
+    output_format: Option<OutputFormat>,
+
+    /// File discarded records and orders are written to, standard error when missing
+    #[arg(long)]
+    rejects: Option<PathBuf>,
+
+    /// Exit with a non-zero code when records failed to be extracted, or records or orders were discarded
+    #[arg(long)]
+    fail_on_discard: bool,
+
+    /// Number of threads transforming records
+    #[arg(long, default_value_t = 1)]
+    workers: usize,
+
+    /// State file allowing an interrupted run to be resumed; not available with a directory or glob pattern source, nor
+    /// with a compressed, JSON array or standard output
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Aborts the run once more records and orders than that have been discarded
+    #[arg(long)]
+    max_discards: Option<u64>,
+
+    /// Merges the orders sharing an id into multi-line orders, either when they follow each other or wherever they are
+    #[arg(long, value_enum)]
+    aggregate: Option<Aggregate>,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum SourceFormat {
+    Csv,
+    Json,
+    Sqlite,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Aggregate {
+    Consecutive,
+    Keyed,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Csv,
+    Json,
+    Ndjson,
+    Sqlite,
+}
+
+fn main() {
+    let arguments = Arguments::parse();
+    match run(&arguments) {
+        Ok(discarded) if discarded && arguments.fail_on_discard => process::exit(DISCARDS),
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("poor-man-etl: {}", e);
+            process::exit(FAILURE);
+        }
+    }
+}
+
+/// Runs the pipeline, telling whether anything was discarded.
+fn run(arguments: &Arguments) -> Result<bool, Box<dyn Error>> {
+    if arguments.workers == 0 {
+        return Err("--workers should be > 0".into());
+    }
+    let resumed = arguments.checkpoint.as_ref().is_some_and(|checkpoint| checkpoint.exists());
+    let mut extractor = create_extractor(arguments)?;
+    let transformer = create_transformer(&arguments.transformer, arguments.unit)?;
+    let mut loader = create_loader(arguments, resumed)?;
+    let reporter = WritingReporter::to(match &arguments.rejects {
+        Some(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+        None => Box::new(io::stderr()),
+    });
+
+    let mut engine = Engine::builder().with_workers(arguments.workers);
+    if let Some(checkpoint) = &arguments.checkpoint {
+        engine = engine.with_checkpoint(checkpoint);
+    }
+    if let Some(max_discards) = arguments.max_discards {
+        engine = engine.with_max_discards(max_discards);
+    }
+    match arguments.aggregate {
+        Some(Aggregate::Consecutive) => engine = engine.with_aggregation(Aggregation::Consecutive),
+        Some(Aggregate::Keyed) => engine = engine.with_aggregation(Aggregation::Keyed),
+        None => {}
+    }
+    let report = match engine.build().run(extractor.as_mut(), transformer.as_ref(), &reporter, loader.as_mut()) {
+        Ok(report) => report,
+        Err(EngineError::DiscardThresholdExceeded(report)) => {
+            eprintln!("{}", report);
+            return Err(Box::new(EngineError::DiscardThresholdExceeded(report)));
+        }
+        Err(e) => return Err(Box::new(e)),
+    };
+    eprintln!("{}", report);
+    Ok(report.failed_extractions() + report.discarded_records() + report.discarded_orders() > 0)
+}
+
+fn create_extractor(arguments: &Arguments) -> Result<Box<dyn Extractor<MapRecord>>, Box<dyn Error>> {
+    let format = match arguments.source_format {
+        Some(format) => format,
+        None => match extension(&arguments.source).as_str() {
+            extension if CSV_EXTENSIONS.contains(&extension) => SourceFormat::Csv,
+            extension if JSON_EXTENSIONS.contains(&extension) => SourceFormat::Json,
+            "db" | "sqlite" | "sqlite3" => SourceFormat::Sqlite,
+            _ => return Err(format!("unknown source format of {}, use --source-format", arguments.source.display()).into()),
+        },
+    };
+    let open = || -> io::Result<Box<dyn Read>> {
+        if is_standard_stream(&arguments.source) {
+            return Ok(Box::new(io::stdin()));
+        }
+        Ok(Box::new(File::open(&arguments.source)?))
+    };
+    let encoding = arguments.encoding;
+    // an existing file is extracted as such, even when its name has glob characters
+    let files = !is_standard_stream(&arguments.source)
+        && (arguments.source.is_dir() || (!arguments.source.exists() && arguments.source.to_string_lossy().contains(GLOB_CHARACTERS)));
+    if files && arguments.checkpoint.is_some() {
+        // record ids are positions across files, which shift as soon as a file is added or removed
+        return Err("a directory or glob pattern source cannot be resumed with --checkpoint".into());
+    }
+    Ok(match format {
+        SourceFormat::Csv if files => {
+            let dialect = Dialect::default().with_encoding(encoding);
+            extract_files(&arguments.source, CSV_EXTENSIONS, move |file| CsvExtractor::with_dialect(file, &dialect))?
+        }
+        SourceFormat::Csv => Box::new(CsvExtractor::with_dialect(open()?, &Dialect::default().with_encoding(encoding))?),
+        SourceFormat::Json if files => extract_files(&arguments.source, JSON_EXTENSIONS, move |file| JsonExtractor::with_encoding(file, encoding))?,
+        SourceFormat::Json => Box::new(JsonExtractor::with_encoding(open()?, encoding)?),
+        SourceFormat::Sqlite if files => return Err("a SQLite source cannot be a directory or a glob pattern".into()),
+        SourceFormat::Sqlite if is_standard_stream(&arguments.source) => return Err("a SQLite source cannot be read from standard input".into()),
+        SourceFormat::Sqlite => {
+            let query = arguments.query.as_ref().ok_or("missing --query for SQLite source")?;
+            Box::new(SqlExtractor::query(Connection::open(&arguments.source)?, query, RecordId::RowNumber)?)
+        }
+    })
+}
+
+/// Chains the files of a source directory having one of the given extensions, or the ones matching a source glob
+/// pattern.
+fn extract_files<E, F>(source: &Path, extensions: &[&str], open: F) -> Result<Box<dyn Extractor<MapRecord>>, Box<dyn Error>>
+    where E: Extractor<MapRecord> + 'static,
+          F: FnMut(File) -> Result<E, Box<dyn Error>> + 'static {
+    Ok(if source.is_dir() {
+        Box::new(FilesExtractor::directory(source, extensions, open)?)
+    } else {
+        Box::new(FilesExtractor::glob(&source.to_string_lossy(), open)?)
+    })
+}
+
+fn create_transformer(transformer: &str, unit: Option<Unit>) -> Result<Box<dyn Transformer<MapRecord> + Sync>, Box<dyn Error>> {
+    Ok(match (transformer, unit) {
+        ("traderjoes", None) => Box::new(TraderJoesTransformer::new()),
+        ("traderjoes", Some(unit)) => Box::new(NormalisingTransformer::to(unit, TraderJoesTransformer::new())),
+        (path, None) => Box::new(MappingTransformer::from_file(path)?),
+        (path, Some(unit)) => Box::new(NormalisingTransformer::to(unit, MappingTransformer::from_file(path)?)),
+    })
+}
+
+/// A resumed run appends to the output of the interrupted one; outputs which cannot be appended to are refused as
+/// soon as a checkpoint is given, rather than once the run has to be resumed.
+fn create_loader(arguments: &Arguments, resumed: bool) -> Result<Box<dyn Loader>, Box<dyn Error>> {
+    let format = match arguments.output_format {
+        Some(format) => format,
+        None => match extension(&arguments.output).as_str() {
+            "csv" => OutputFormat::Csv,
+            "json" => OutputFormat::Json,
+            "ndjson" | "jsonl" => OutputFormat::Ndjson,
+            "db" | "sqlite" | "sqlite3" => OutputFormat::Sqlite,
+            _ => return Err(format!("unknown output format of {}, use --output-format", arguments.output.display()).into()),
+        },
+    };
+    let checkpointed = arguments.checkpoint.is_some();
+    if is_standard_stream(&arguments.output) {
+        if checkpointed {
+            return Err("a standard output cannot be resumed with --checkpoint".into());
+        }
+        if format == OutputFormat::Sqlite {
+            return Err("a SQLite output cannot be written to standard output".into());
+        }
+    }
+    let compression = Compression::of(&arguments.output);
+    if compression != Compression::None {
+        if checkpointed {
+            return Err("a compressed output cannot be resumed with --checkpoint".into());
+        }
+        if format == OutputFormat::Sqlite {
+            return Err("a SQLite output cannot be compressed".into());
+        }
+    }
+    if format == OutputFormat::Json && checkpointed {
+        return Err("a JSON array output cannot be resumed with --checkpoint, use NDJSON instead".into());
+    }
+    // without a checkpoint, a file output is staged so that an aborted run leaves no partial output behind; with
+    // one, the output of an interrupted run is kept so that the next run can resume it
+    let open = || -> io::Result<(Box<dyn Write>, Option<Staging>)> {
+        if is_standard_stream(&arguments.output) {
+            return Ok((Box::new(io::stdout()), None));
+        }
+        if arguments.checkpoint.is_none() {
+            let (file, staging) = Staging::create(&arguments.output)?;
+            return Ok((Box::new(file), Some(staging)));
+        }
+        let file = OpenOptions::new().write(true).create(true).append(resumed).truncate(!resumed).open(&arguments.output)?;
+        Ok((Box::new(file), None))
+    };
+    Ok(match format {
+        OutputFormat::Csv if resumed => Box::new(CsvLoader::append(open()?.0)?),
+        OutputFormat::Csv => {
+            let (writer, staging) = open()?;
+            let loader = CsvLoader::with_compression(writer, &Dialect::default(), compression)?;
+            match staging {
+                Some(staging) => Box::new(loader.staged(staging)),
+                None => Box::new(loader),
+            }
+        }
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            let format = if format == OutputFormat::Json { Format::Array } else { Format::Lines };
+            let (writer, staging) = open()?;
+            let loader = JsonLoader::with_compression(writer, format, compression)?;
+            match staging {
+                Some(staging) => Box::new(loader.staged(staging)),
+                None => Box::new(loader),
+            }
+        }
+        OutputFormat::Sqlite => Box::new(SqliteLoader::to(Connection::open(&arguments.output)?)?),
+    })
+}
+
+fn is_standard_stream(path: &Path) -> bool {
+    path == Path::new(STANDARD_STREAM)
+}
+
+/// Extension telling the format of a file, looking past the one of its compression (`orders.csv.gz` is a CSV file).
+fn extension(path: &Path) -> String {
+    let path = match Compression::of(path) {
+        Compression::None => path,
+        _ => Path::new(path.file_stem().unwrap_or_default()),
+    };
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Writes every discard as a line of text, with a line per error of a discarded record. Records are located in their
+/// source file when extracted from several ones, and orders by the lines of the records they were transformed from.
+struct WritingReporter {
+    writer: RefCell<Box<dyn Write>>,
+}
+
+impl WritingReporter {
+    fn to(writer: Box<dyn Write>) -> Self {
+        WritingReporter { writer: RefCell::new(writer) }
+    }
+}
+
+impl Reporter for WritingReporter {
+    fn report_record(&self, discarded_record: DiscardedRecord) {
+        let mut writer = self.writer.borrow_mut();
+        let record = located(discarded_record.id(), discarded_record.provenance());
+        for error in discarded_record.errors() {
+            let _ = writeln!(writer, "{}: {}", record, error);
+        }
+    }
+
+    fn report_order(&self, discarded_order: DiscardedOrder) {
+        let order = traced(discarded_order.order().id(), discarded_order.provenances());
+        let _ = writeln!(self.writer.borrow_mut(), "{}: {}", order, discarded_order.error_message());
+    }
+
+    fn report_extraction(&self, extractor_error: ExtractorError) {
+        let record = located(extractor_error.position(), extractor_error.provenance());
+        let _ = writeln!(self.writer.borrow_mut(), "{}: {}", record, extractor_error.message());
+    }
+}
+
+fn located(id: u64, provenance: &Provenance) -> String {
+    match (provenance.source(), provenance.line()) {
+        (Some(source), Some(line)) => format!("record {} ({}:{})", id, source, line),
+        (Some(source), None) => format!("record {} ({})", id, source),
+        (None, _) => format!("record {}", id),
+    }
+}
+
+fn traced(id: u64, provenances: &[Provenance]) -> String {
+    let records: Vec<String> = provenances.iter()
+        .filter_map(|provenance| match (provenance.source(), provenance.line()) {
+            (Some(source), Some(line)) => Some(format!("{}:{}", source, line)),
+            (Some(source), None) => Some(source.to_string()),
+            (None, Some(line)) => Some(format!("line {}", line)),
+            (None, None) => None,
+        })
+        .collect();
+    if records.is_empty() {
+        format!("order {}", id)
+    } else {
+        format!("order {} ({})", id, records.join(", "))
+    }
+}
@@ -0,0 +1 @@
+pub mod transformer;
\ No newline at end of file
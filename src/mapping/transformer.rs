@@ -0,0 +1,638 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use regex::Regex;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::Zero;
+use serde::Deserialize;
+
+use crate::order::{Currency, Order, OrderBuilder, Quantity, Unit};
+use crate::record::Record;
+use crate::transformer::{DiscardedRecord, FieldError, RecordErrorKind, Transformer};
+use crate::value::{CoercionError, Value};
+
+/// Transformer driven by a TOML mapping file, which tells which source columns feed each order field, how dates are
+/// parsed and which validation rules apply, e.g.:
+///
+/// ```toml
+/// [id]
+/// column = "Order Number"
+/// error = "Invalid order number"
+/// rules = [{ positive = true }]
+///
+/// [date]
+/// parts = ["Year", "Month", "Day"]  # or `column = "Date"`
+/// separator = "-"                   # parts are joined with it, `-` by default
+/// format = "%Y-%m-%d"
+/// error = "Invalid date."
+///
+/// [product_id]
+/// column = "Product Number"
+/// error = "Invalid product number."
+/// rules = [{ regex = "^[0-9]+$", error = "Product number should be numeric." }]
+/// ```
+///
+/// `product_name` and `quantity` follow the same layout. Rules are `regex`, `charset` (`alphabetic`,
+/// `alphanumeric`, `numeric` or `ascii`) and, for the id and the quantity, `positive`; each one may override the
+/// error of its field. Every field is validated, a record being discarded with the errors of all the failing ones.
+///
+/// The quantity also takes either a fixed `unit` (`kg` when missing) or a `unit_column` the unit is read from, an
+/// unknown unit failing the field.
+///
+/// An optional `price` table maps the unit price like the other fields, along with either a fixed `currency` or a
+/// `currency_column`, and an optional `total_column` checked against the unit price times the quantity.
+pub struct MappingTransformer {
+    id: Field,
+    date: DateField,
+    product_id: Field,
+    product_name: Field,
+    quantity: QuantityField,
+    price: Option<PriceField>,
+}
+
+impl MappingTransformer {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        MappingTransformer::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(mapping: &str) -> Result<Self, Box<dyn Error>> {
+        let mapping: Mapping = toml::from_str(mapping)?;
+        Ok(MappingTransformer {
+            id: Field::compile("id", mapping.id, true)?,
+            date: DateField::compile(mapping.date)?,
+            product_id: Field::compile("product_id", mapping.product_id, false)?,
+            product_name: Field::compile("product_name", mapping.product_name, false)?,
+            quantity: QuantityField::compile(mapping.quantity)?,
+            price: mapping.price.map(PriceField::compile).transpose()?,
+        })
+    }
+
+    fn map<R: Record>(&self, record: &R) -> Result<Order, Vec<FieldError>> {
+        let mut errors = Vec::new();
+        let id = checked("id", self.id.number::<R, u64>(record)
+            .and_then(|id| if id > 0 { Ok(id) } else { Err(self.id.failure(RecordErrorKind::Invalid)) }), &mut errors);
+        let date = checked("date", self.date.date(record), &mut errors);
+        let product_id = checked("product_id", self.product_id.text(record), &mut errors);
+        let product_name = checked("product_name", self.product_name.text(record), &mut errors);
+        let quantity = checked("quantity", self.quantity.quantity(record), &mut errors);
+        let price = match &self.price {
+            Some(price) => checked("price", price.price(record), &mut errors),
+            None => None,
+        };
+
+        let (id, date, product_id, product_name, quantity) = match (id, date, product_id, product_name, quantity) {
+            (Some(id), Some(date), Some(product_id), Some(product_name), Some(quantity)) if errors.is_empty() =>
+                (id, date, product_id, product_name, quantity),
+            _ => return Err(errors),
+        };
+        let mut builder = Order::builder()
+            .with_id(id)
+            .with_date(date)
+            .with_product_id(product_id.to_owned())
+            .with_product_name(product_name.to_owned())
+            .with_quantity(quantity);
+        if let Some(price) = price {
+            builder = price.apply(builder);
+        }
+
+        // every field is valid on its own, so only a total not matching the price, or out of range, can fail the order
+        builder.try_build().map_err(|_| {
+            let error = self.price.as_ref().map_or_else(String::new, |price| price.field.error.clone());
+            vec![FieldError::new("price", RecordErrorKind::Invalid, error)]
+        })
+    }
+}
+
+impl<R: Record> Transformer<R> for MappingTransformer {
+    fn transform(&self, record: R) -> Result<Order, DiscardedRecord> {
+        self.map(&record).map_err(|errors| DiscardedRecord::with_errors(&record, errors))
+    }
+}
+
+/// Reason a field is rejected, along with its error message.
+type Failure = (RecordErrorKind, String);
+
+/// Keeps the value of a valid field, recording the error of an invalid one.
+fn checked<T>(field: &str, value: Result<T, Failure>, errors: &mut Vec<FieldError>) -> Option<T> {
+    value.map_err(|(kind, message)| errors.push(FieldError::new(field, kind, message))).ok()
+}
+
+#[derive(Debug)]
+pub struct MappingError {
+    message: String
+}
+
+impl MappingError {
+    pub fn new(message: &str) -> MappingError {
+        MappingError { message: message.to_string() }
+    }
+}
+
+impl fmt::Display for MappingError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.message)
+    }
+}
+
+impl Error for MappingError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Mapping {
+    id: FieldMapping,
+    date: DateMapping,
+    product_id: FieldMapping,
+    product_name: FieldMapping,
+    quantity: QuantityMapping,
+    price: Option<PriceMapping>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FieldMapping {
+    column: String,
+    error: String,
+    #[serde(default)]
+    rules: Vec<RuleMapping>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuantityMapping {
+    column: String,
+    error: String,
+    #[serde(default)]
+    rules: Vec<RuleMapping>,
+    unit: Option<String>,
+    unit_column: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PriceMapping {
+    column: String,
+    error: String,
+    #[serde(default)]
+    rules: Vec<RuleMapping>,
+    currency: Option<String>,
+    currency_column: Option<String>,
+    total_column: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DateMapping {
+    column: Option<String>,
+    #[serde(default)]
+    parts: Vec<String>,
+    separator: Option<String>,
+    format: String,
+    error: String,
+    #[serde(default)]
+    rules: Vec<RuleMapping>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleMapping {
+    regex: Option<String>,
+    charset: Option<Charset>,
+    positive: Option<bool>,
+    error: Option<String>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Charset {
+    Alphabetic,
+    Alphanumeric,
+    Numeric,
+    Ascii,
+}
+
+impl Charset {
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            Charset::Alphabetic => value.chars().all(char::is_alphabetic),
+            Charset::Alphanumeric => value.chars().all(char::is_alphanumeric),
+            Charset::Numeric => value.chars().all(char::is_numeric),
+            Charset::Ascii => value.is_ascii(),
+        }
+    }
+}
+
+enum Rule {
+    Regex(Regex, Option<String>),
+    Charset(Charset, Option<String>),
+    Positive(Option<String>),
+}
+
+impl Rule {
+    fn compile(field: &str, rule: RuleMapping, numeric: bool) -> Result<Rule, Box<dyn Error>> {
+        match rule {
+            RuleMapping { regex: Some(regex), charset: None, positive: None, error } => Ok(Rule::Regex(Regex::new(&regex)?, error)),
+            RuleMapping { regex: None, charset: Some(charset), positive: None, error } => Ok(Rule::Charset(charset, error)),
+            RuleMapping { regex: None, charset: None, positive: Some(true), error } if numeric => Ok(Rule::Positive(error)),
+            RuleMapping { regex: None, charset: None, positive: Some(_), .. } if !numeric =>
+                Err(Box::new(MappingError::new(&format!("{}: positive rule only applies to id and quantity", field)))),
+            _ => Err(Box::new(MappingError::new(&format!("{}: a rule should have exactly one of regex, charset or positive = true", field)))),
+        }
+    }
+
+    /// Checks the raw value, telling the rule specific error if any.
+    fn check_text(&self, value: &str) -> Result<(), Option<&String>> {
+        match self {
+            Rule::Regex(regex, error) if !regex.is_match(value) => Err(error.as_ref()),
+            Rule::Charset(charset, error) if !charset.accepts(value) => Err(error.as_ref()),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_number<N: PartialOrd + Zero>(&self, value: &N) -> Result<(), Option<&String>> {
+        match self {
+            Rule::Positive(error) if value <= &N::zero() => Err(error.as_ref()),
+            _ => Ok(()),
+        }
+    }
+}
+
+struct Field {
+    column: String,
+    error: String,
+    rules: Vec<Rule>,
+}
+
+impl Field {
+    fn compile(name: &str, mapping: FieldMapping, numeric: bool) -> Result<Field, Box<dyn Error>> {
+        let rules = mapping.rules.into_iter()
+            .map(|rule| Rule::compile(name, rule, numeric))
+            .collect::<Result<_, _>>()?;
+        Ok(Field { column: mapping.column, error: mapping.error, rules })
+    }
+
+    fn text<'r, R: Record>(&self, record: &'r R) -> Result<&'r String, Failure> {
+        let value = record.value_for(&self.column).ok_or_else(|| self.failure(RecordErrorKind::Missing))?;
+        check(&self.rules, &self.error, |rule| rule.check_text(value))?;
+        Ok(value)
+    }
+
+    /// Checks the text of the value against the text rules, and then coerces its typed value as the built-in
+    /// transformers do, so that a mapping accepts the same numbers.
+    fn number<R: Record, N: Number>(&self, record: &R) -> Result<N, Failure> {
+        self.text(record)?;
+        let value = record.typed_value_for(&self.column).ok_or_else(|| self.failure(RecordErrorKind::Missing))?;
+        let value = N::coerce(&value).map_err(|_| self.failure(RecordErrorKind::Malformed))?;
+        check(&self.rules, &self.error, |rule| rule.check_number(&value))?;
+        Ok(value)
+    }
+
+    fn failure(&self, kind: RecordErrorKind) -> Failure {
+        (kind, self.error.clone())
+    }
+}
+
+/// Number a field value is coerced to.
+trait Number: PartialOrd + Zero + Sized {
+    fn coerce(value: &Value) -> Result<Self, CoercionError>;
+}
+
+impl Number for u64 {
+    fn coerce(value: &Value) -> Result<Self, CoercionError> {
+        value.to_uint()
+    }
+}
+
+impl Number for Decimal {
+    fn coerce(value: &Value) -> Result<Self, CoercionError> {
+        value.to_decimal()
+    }
+}
+
+enum UnitSource {
+    Fixed(Unit),
+    Column(String),
+}
+
+struct QuantityField {
+    field: Field,
+    unit: UnitSource,
+}
+
+impl QuantityField {
+    fn compile(mapping: QuantityMapping) -> Result<QuantityField, Box<dyn Error>> {
+        let unit = match (mapping.unit, mapping.unit_column) {
+            (Some(unit), None) => UnitSource::Fixed(unit.parse()
+                .map_err(|e| MappingError::new(&format!("quantity: {}", e)))?),
+            (None, Some(column)) => UnitSource::Column(column),
+            (None, None) => UnitSource::Fixed(Unit::KG),
+            _ => return Err(Box::new(MappingError::new("quantity: at most one of unit or unit_column should be given"))),
+        };
+        let field = Field::compile("quantity", FieldMapping {
+            column: mapping.column,
+            error: mapping.error,
+            rules: mapping.rules,
+        }, true)?;
+        Ok(QuantityField { field, unit })
+    }
+
+    fn quantity<R: Record>(&self, record: &R) -> Result<Quantity, Failure> {
+        let quantity = self.field.number::<R, Decimal>(record)?;
+        let unit = match &self.unit {
+            UnitSource::Fixed(unit) => *unit,
+            UnitSource::Column(column) => parse_column(record, column, &self.field)?,
+        };
+        Quantity::builder()
+            .with_quantity(quantity)
+            .with_unit(unit)
+            .try_build()
+            .map_err(|_| self.field.failure(RecordErrorKind::Invalid))
+    }
+}
+
+enum CurrencySource {
+    Fixed(Currency),
+    Column(String),
+}
+
+struct PriceField {
+    field: Field,
+    currency: CurrencySource,
+    total_column: Option<String>,
+}
+
+impl PriceField {
+    fn compile(mapping: PriceMapping) -> Result<PriceField, Box<dyn Error>> {
+        let currency = match (mapping.currency, mapping.currency_column) {
+            (Some(currency), None) => CurrencySource::Fixed(currency.parse()
+                .map_err(|e| MappingError::new(&format!("price: {}", e)))?),
+            (None, Some(column)) => CurrencySource::Column(column),
+            _ => return Err(Box::new(MappingError::new("price: exactly one of currency or currency_column should be given"))),
+        };
+        let field = Field::compile("price", FieldMapping {
+            column: mapping.column,
+            error: mapping.error,
+            rules: mapping.rules,
+        }, true)?;
+        Ok(PriceField { field, currency, total_column: mapping.total_column })
+    }
+
+    fn price<R: Record>(&self, record: &R) -> Result<PriceValue, Failure> {
+        let unit_price = self.field.number::<R, Decimal>(record)?;
+        let currency = match &self.currency {
+            CurrencySource::Fixed(currency) => currency.clone(),
+            CurrencySource::Column(column) => parse_column(record, column, &self.field)?,
+        };
+        let total = match &self.total_column {
+            Some(column) => Some(parse_column(record, column, &self.field)?),
+            None => None,
+        };
+        Ok(PriceValue { unit_price, currency, total })
+    }
+}
+
+struct PriceValue {
+    unit_price: Decimal,
+    currency: Currency,
+    total: Option<Decimal>,
+}
+
+impl PriceValue {
+    fn apply(self, builder: OrderBuilder) -> OrderBuilder {
+        let builder = builder.with_unit_price(self.unit_price).with_currency(self.currency);
+        match self.total {
+            Some(total) => builder.with_total(total),
+            None => builder,
+        }
+    }
+}
+
+struct DateField {
+    columns: Vec<String>,
+    separator: String,
+    format: String,
+    error: String,
+    rules: Vec<Rule>,
+}
+
+impl DateField {
+    fn compile(mapping: DateMapping) -> Result<DateField, Box<dyn Error>> {
+        let columns = match (mapping.column, mapping.parts.is_empty()) {
+            (Some(column), true) => vec![column],
+            (None, false) => mapping.parts,
+            _ => return Err(Box::new(MappingError::new("date: exactly one of column or parts should be given"))),
+        };
+        let rules = mapping.rules.into_iter()
+            .map(|rule| Rule::compile("date", rule, false))
+            .collect::<Result<_, _>>()?;
+        Ok(DateField {
+            columns,
+            separator: mapping.separator.unwrap_or_else(|| "-".to_string()),
+            format: mapping.format,
+            error: mapping.error,
+            rules,
+        })
+    }
+
+    fn date<R: Record>(&self, record: &R) -> Result<NaiveDate, Failure> {
+        let parts = self.columns.iter()
+            .map(|column| record.value_for(column).map(String::as_str))
+            .collect::<Option<Vec<&str>>>()
+            .ok_or_else(|| (RecordErrorKind::Missing, self.error.clone()))?;
+        let value = parts.join(&self.separator);
+        check(&self.rules, &self.error, |rule| rule.check_text(&value))?;
+        NaiveDate::parse_from_str(&value, &self.format).map_err(|_| (RecordErrorKind::Malformed, self.error.clone()))
+    }
+}
+
+/// Parses a column completing a field, such as the unit of the quantity, failing with the error of the field.
+fn parse_column<R: Record, T: FromStr>(record: &R, column: &str, field: &Field) -> Result<T, Failure> {
+    let value = record.value_for(column).ok_or_else(|| field.failure(RecordErrorKind::Missing))?;
+    value.parse().map_err(|_| field.failure(RecordErrorKind::Malformed))
+}
+
+fn check<F>(rules: &[Rule], error: &str, check: F) -> Result<(), Failure>
+    where F: Fn(&Rule) -> Result<(), Option<&String>> {
+    rules.iter()
+        .try_for_each(check)
+        .map_err(|rule_error| (RecordErrorKind::Invalid, rule_error.map_or_else(|| error.to_string(), String::to_owned)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record::MapRecord;
+    use crate::traderjoes::transformer::TraderJoesTransformer;
+
+    use super::*;
+
+    const TRADER_JOES: &str = include_str!("../../mappings/traderjoes.toml");
+
+    #[test]
+    fn traderjoes_mapping_should_match_traderjoes_transformer() {
+        let mapping_transformer = MappingTransformer::parse(TRADER_JOES).unwrap();
+        let traderjoes_transformer = TraderJoesTransformer::new();
+        let records = vec![
+            traderjoes_record("1", "2019", "8", "24", "12345", "Jam", "100.20"),
+            traderjoes_record("0", "2019", "8", "24", "12345", "Jam", "100.20"),
+            traderjoes_record("x", "2019", "8", "24", "12345", "Jam", "100.20"),
+            traderjoes_record("1", "2019", "13", "24", "12345", "Jam", "100.20"),
+            traderjoes_record("1", "2019", "8", "24", "123-45", "Jam", "100.20"),
+            traderjoes_record("1", "2019", "8", "24", "12345", "Jam 2", "100.20"),
+            traderjoes_record("1", "2019", "8", "24", "12345", "", "100.20"),
+            traderjoes_record("1", "2019", "8", "24", "12345", "Jam", "0"),
+            traderjoes_record("1", "2019", "8", "24", "12345", "Jam", "-1"),
+            traderjoes_record("1", "2019", "8", "24", "12345", "Jam", "many"),
+            traderjoes_record(" 13", "2019", "8", "24", "12345", "Jam", "1.2e1"),
+            traderjoes_record("18446744073709551615", "2019", "8", "24", "12345", "Jam", " 12 "),
+            traderjoes_record("1.0", "2019", "8", "24", "12345", "Jam", "1e-1"),
+            MapRecord::with_typed_columns(1, vec![
+                ("Order Number".to_string(), Value::Int(13)),
+                ("Year".to_string(), Value::Int(2019)),
+                ("Month".to_string(), Value::Int(8)),
+                ("Day".to_string(), Value::Int(24)),
+                ("Product Number".to_string(), Value::Int(12345)),
+                ("Product Name".to_string(), Value::from("Jam")),
+                ("Count".to_string(), Value::Decimal(Decimal::new(1220, 2))),
+            ]),
+            MapRecord::with_typed_columns(1, vec![
+                ("Order Number".to_string(), Value::Int(-13)),
+                ("Product Name".to_string(), Value::Null),
+                ("Count".to_string(), Value::Bool(true)),
+            ]),
+            MapRecord::new(1, vec![].into_iter().collect()),
+        ];
+
+        for record in records {
+            let expected = Transformer::<MapRecord>::transform(&traderjoes_transformer, record.clone())
+                .map_err(|discarded_record| discarded_record.errors().to_vec());
+            let actual = mapping_transformer.transform(record)
+                .map_err(|discarded_record| discarded_record.errors().to_vec());
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn should_use_rule_error() {
+        let transformer = MappingTransformer::parse(&TRADER_JOES.replace(
+            "rules = [{ charset = \"alphanumeric\" }]",
+            "rules = [{ regex = \"^[0-9]+$\", error = \"Product number should be numeric.\" }]")).unwrap();
+
+        let result = transformer.transform(traderjoes_record("1", "2019", "8", "24", "A12345", "Jam", "1"));
+
+        assert_eq!(result.err().unwrap().error_message(), "Product number should be numeric.");
+    }
+
+    #[test]
+    fn should_parse_date_from_single_column() {
+        let transformer = MappingTransformer::parse(&TRADER_JOES.replace(
+            "parts = [\"Year\", \"Month\", \"Day\"]\nformat = \"%Y-%m-%d\"",
+            "column = \"Year\"\nformat = \"%d/%m/%Y\"")).unwrap();
+
+        let order = transformer.transform(traderjoes_record("1", "24/08/2019", "", "", "12345", "Jam", "1")).unwrap();
+
+        assert_eq!(order.date(), &NaiveDate::from_ymd_opt(2019, 8, 24).unwrap());
+    }
+
+    #[test]
+    fn should_reject_positive_rule_on_text_field() {
+        let result = MappingTransformer::parse(&TRADER_JOES.replace(
+            "rules = [{ charset = \"alphabetic\" }]",
+            "rules = [{ positive = true }]"));
+
+        assert_eq!(result.err().unwrap().to_string(), "product_name: positive rule only applies to id and quantity");
+    }
+
+    #[test]
+    fn should_reject_date_with_column_and_parts() {
+        let result = MappingTransformer::parse(&TRADER_JOES.replace(
+            "format = \"%Y-%m-%d\"",
+            "column = \"Date\"\nformat = \"%Y-%m-%d\""));
+
+        assert_eq!(result.err().unwrap().to_string(), "date: exactly one of column or parts should be given");
+    }
+
+    #[test]
+    fn should_read_unit_from_column() {
+        let transformer = MappingTransformer::parse(&TRADER_JOES.replace(
+            "unit = \"each\"",
+            "unit_column = \"Unit\"")).unwrap();
+        let record = |unit: &str| MapRecord::new(1, vec![
+            ("Order Number".to_string(), "1".to_string()),
+            ("Year".to_string(), "2019".to_string()),
+            ("Month".to_string(), "8".to_string()),
+            ("Day".to_string(), "24".to_string()),
+            ("Product Number".to_string(), "12345".to_string()),
+            ("Product Name".to_string(), "Jam".to_string()),
+            ("Count".to_string(), "2".to_string()),
+            ("Unit".to_string(), unit.to_string()),
+        ].into_iter().collect());
+
+        let order = transformer.transform(record("lb")).unwrap();
+        let result = transformer.transform(record("stone"));
+
+        assert_eq!(order.line_items()[0].quantity().unit(), &Unit::LB);
+        assert_eq!(result.err().unwrap().error_message(), "Invalid count.");
+    }
+
+    #[test]
+    fn should_reject_unknown_unit() {
+        let result = MappingTransformer::parse(&TRADER_JOES.replace("unit = \"each\"", "unit = \"stone\""));
+
+        assert_eq!(result.err().unwrap().to_string(), "quantity: unknown unit stone");
+    }
+
+    #[test]
+    fn should_discard_non_positive_values_without_positive_rule() {
+        let transformer = MappingTransformer::parse(&TRADER_JOES.replace("rules = [{ positive = true }]\n", "")).unwrap();
+
+        let zero_id = transformer.transform(traderjoes_record("0", "2019", "8", "24", "12345", "Jam", "1"));
+        let zero_count = transformer.transform(traderjoes_record("1", "2019", "8", "24", "12345", "Jam", "0"));
+
+        assert_eq!(zero_id.err().unwrap().error_message(), "Invalid order number");
+        assert_eq!(zero_count.err().unwrap().error_message(), "Invalid count.");
+    }
+
+    #[test]
+    fn should_map_price() {
+        let transformer = MappingTransformer::parse(&format!("{}\n\
+            [price]\n\
+            column = \"Price\"\n\
+            currency = \"usd\"\n\
+            total_column = \"Total\"\n\
+            error = \"Invalid price.\"\n", TRADER_JOES)).unwrap();
+        let record = |total: &str| MapRecord::new(1, vec![
+            ("Order Number".to_string(), "1".to_string()),
+            ("Year".to_string(), "2019".to_string()),
+            ("Month".to_string(), "8".to_string()),
+            ("Day".to_string(), "24".to_string()),
+            ("Product Number".to_string(), "12345".to_string()),
+            ("Product Name".to_string(), "Jam".to_string()),
+            ("Count".to_string(), "2".to_string()),
+            ("Price".to_string(), "1.25".to_string()),
+            ("Total".to_string(), total.to_string()),
+        ].into_iter().collect());
+
+        let order = transformer.transform(record("2.50")).unwrap();
+        let result = transformer.transform(record("2.40"));
+
+        assert_eq!(order.line_items()[0].price().unwrap().unit_price(), &Decimal::new(125, 2));
+        assert_eq!(order.line_items()[0].price().unwrap().currency().code(), "USD");
+        assert_eq!(order.line_items()[0].price().unwrap().total(), &Decimal::new(250, 2));
+        assert_eq!(result.err().unwrap().error_message(), "Invalid price.");
+    }
+
+    fn traderjoes_record(order_number: &str, year: &str, month: &str, day: &str, product_number: &str, product_name: &str, count: &str) -> MapRecord {
+        MapRecord::new(1, vec![
+            ("Order Number".to_string(), order_number.to_string()),
+            ("Year".to_string(), year.to_string()),
+            ("Month".to_string(), month.to_string()),
+            ("Day".to_string(), day.to_string()),
+            ("Product Number".to_string(), product_number.to_string()),
+            ("Product Name".to_string(), product_name.to_string()),
+            ("Count".to_string(), count.to_string()),
+        ].into_iter().collect())
+    }
+}
@@ -1,17 +1,23 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
 use chrono::NaiveDate;
 use inflections::case::to_title_case;
-use rust_decimal::Decimal;
+use rust_decimal::{Decimal, RoundingStrategy};
 use rust_decimal::prelude::Zero;
 
 use crate::order::Unit::KG;
+use crate::record::Provenance;
 
+/// Order made of one or more line items, sharing its id and date, along with the provenance of the records it was
+/// transformed from.
 #[derive(Debug, Eq, PartialEq)]
 pub struct Order {
     id: u64,
     date: NaiveDate,
-    product_id: String,
-    product_name: String,
-    quantity: Quantity,
+    line_items: Vec<LineItem>,
+    provenances: Vec<Provenance>,
 }
 
 impl Order {
@@ -19,9 +25,8 @@ impl Order {
         OrderBuilder {
             id: None,
             date: None,
-            product_id: None,
-            product_name: None,
-            quantity: None,
+            line_item: LineItem::builder(),
+            line_items: Vec::new(),
         }
     }
 
@@ -33,6 +38,60 @@ impl Order {
         &self.date
     }
 
+    pub fn line_items(&self) -> &[LineItem] {
+        &self.line_items
+    }
+
+    /// Provenance of the records the order was transformed from, in source order.
+    pub fn provenances(&self) -> &[Provenance] {
+        &self.provenances
+    }
+
+    /// Adds the provenance of a record the order was transformed from.
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenances.push(provenance);
+        self
+    }
+
+    /// Converts the quantities of all the line items to the given unit.
+    pub fn normalise_to(mut self, unit: Unit) -> Result<Order, UnitError> {
+        self.line_items = self.line_items.into_iter()
+            .map(|line_item| line_item.normalise_to(unit))
+            .collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    /// Appends the line items of another order with the same id and date, handing it back when they differ.
+    pub fn merge(&mut self, other: Order) -> Result<(), Order> {
+        if other.id != self.id || other.date != self.date {
+            return Err(other);
+        }
+        self.line_items.extend(other.line_items);
+        self.provenances.extend(other.provenances);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct LineItem {
+    product_id: String,
+    product_name: String,
+    quantity: Quantity,
+    price: Option<Price>,
+}
+
+impl LineItem {
+    pub fn builder() -> LineItemBuilder {
+        LineItemBuilder {
+            product_id: None,
+            product_name: None,
+            quantity: None,
+            unit_price: None,
+            currency: None,
+            total: None,
+        }
+    }
+
     pub fn product_id(&self) -> &str {
         &self.product_id
     }
@@ -44,19 +103,100 @@ impl Order {
     pub fn quantity(&self) -> &Quantity {
         &self.quantity
     }
+
+    pub fn price(&self) -> Option<&Price> {
+        self.price.as_ref()
+    }
+
+    /// Converts the quantity to the given unit, the unit price following so that the total is unchanged.
+    pub fn normalise_to(mut self, unit: Unit) -> Result<LineItem, UnitError> {
+        let quantity = self.quantity.convert_to(unit)?;
+        let from = self.quantity.unit;
+        if let Some(price) = &mut self.price {
+            price.unit_price = price.unit_price.checked_mul(unit.factor())
+                .and_then(|unit_price| unit_price.checked_div(from.factor()))
+                .ok_or(UnitError::Overflow(from, unit))?
+                .normalize();
+        }
+        self.quantity = quantity;
+        Ok(self)
+    }
+}
+
+/// Price of a line item, the total being the unit price times the quantity rounded to the minor unit of the currency.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Price {
+    unit_price: Decimal,
+    currency: Currency,
+    total: Decimal,
+}
+
+impl Price {
+    pub fn unit_price(&self) -> &Decimal {
+        &self.unit_price
+    }
+
+    pub fn currency(&self) -> &Currency {
+        &self.currency
+    }
+
+    pub fn total(&self) -> &Decimal {
+        &self.total
+    }
+}
+
+/// ISO 4217 currency code.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Currency {
+    code: String,
 }
 
+impl Currency {
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Number of decimals amounts in the currency have: 2 for most of them, 0 for e.g. JPY and 3 for e.g. KWD.
+    pub fn minor_units(&self) -> u32 {
+        match self.code.as_str() {
+            "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX" | "VND" | "VUV"
+            | "XAF" | "XOF" | "XPF" => 0,
+            "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+            _ => 2,
+        }
+    }
+
+    /// Rounds an amount to the minor unit of the currency, half away from zero.
+    pub fn round(&self, amount: Decimal) -> Decimal {
+        let mut rounded = amount.round_dp_with_strategy(self.minor_units(), RoundingStrategy::MidpointAwayFromZero);
+        rounded.rescale(self.minor_units());
+        rounded
+    }
+}
+
+impl FromStr for Currency {
+    type Err = ValidationError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let code = code.to_uppercase();
+        if code.len() != 3 || !code.chars().all(|x| x.is_ascii_alphabetic()) {
+            return Err(ValidationError::InvalidCurrency(code));
+        }
+        Ok(Currency { code })
+    }
+}
+
+/// Builds an order. The product, quantity and price setters describe its first line item; further ones are added with
+/// `with_line_item`.
 pub struct OrderBuilder {
     id: Option<u64>,
     date: Option<NaiveDate>,
-    product_id: Option<String>,
-    product_name: Option<String>,
-    quantity: Option<Quantity>,
+    line_item: LineItemBuilder,
+    line_items: Vec<LineItem>,
 }
 
 impl OrderBuilder {
     pub fn with_id(mut self, id: u64) -> Self {
-        assert!(id > 0, "id should be > 0");
         self.id = Some(id);
         self
     }
@@ -66,6 +206,70 @@ impl OrderBuilder {
         self
     }
 
+    pub fn with_product_id(mut self, product_id: String) -> Self {
+        self.line_item = self.line_item.with_product_id(product_id);
+        self
+    }
+
+    pub fn with_product_name(mut self, product_name: String) -> Self {
+        self.line_item = self.line_item.with_product_name(product_name);
+        self
+    }
+
+    pub fn with_quantity(mut self, quantity: Quantity) -> Self {
+        self.line_item = self.line_item.with_quantity(quantity);
+        self
+    }
+
+    pub fn with_unit_price(mut self, unit_price: Decimal) -> Self {
+        self.line_item = self.line_item.with_unit_price(unit_price);
+        self
+    }
+
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        self.line_item = self.line_item.with_currency(currency);
+        self
+    }
+
+    pub fn with_total(mut self, total: Decimal) -> Self {
+        self.line_item = self.line_item.with_total(total);
+        self
+    }
+
+    pub fn with_line_item(mut self, line_item: LineItem) -> Self {
+        self.line_items.push(line_item);
+        self
+    }
+
+    /// Builds the order, panicking when it is invalid.
+    pub fn build(self) -> Order {
+        self.try_build().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_build(self) -> Result<Order, ValidationError> {
+        let id = self.id.ok_or(ValidationError::MissingField("id"))?;
+        if id == 0 {
+            return Err(ValidationError::NonPositiveId);
+        }
+        let date = self.date.ok_or(ValidationError::MissingField("date"))?;
+        let mut line_items = self.line_items;
+        if line_items.is_empty() || self.line_item.is_started() {
+            line_items.insert(0, self.line_item.try_build()?);
+        }
+        Ok(Order { id, date, line_items, provenances: Vec::new() })
+    }
+}
+
+pub struct LineItemBuilder {
+    product_id: Option<String>,
+    product_name: Option<String>,
+    quantity: Option<Quantity>,
+    unit_price: Option<Decimal>,
+    currency: Option<Currency>,
+    total: Option<Decimal>,
+}
+
+impl LineItemBuilder {
     pub fn with_product_id(mut self, product_id: String) -> Self {
         self.product_id = Some(product_id);
         self
@@ -81,14 +285,55 @@ impl OrderBuilder {
         self
     }
 
-    pub fn build(self) -> Order {
-        Order {
-            id: self.id.expect("missing id"),
-            date: self.date.expect("missing date"),
-            product_id: self.product_id.expect("missing product id"),
-            product_name: to_title_case(&self.product_name.expect("missing product name")),
-            quantity: self.quantity.expect("missing quantity"),
-        }
+    pub fn with_unit_price(mut self, unit_price: Decimal) -> Self {
+        self.unit_price = Some(unit_price);
+        self
+    }
+
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        self.currency = Some(currency);
+        self
+    }
+
+    /// Total stated by the source, checked against the one computed from the unit price and the quantity.
+    pub fn with_total(mut self, total: Decimal) -> Self {
+        self.total = Some(total);
+        self
+    }
+
+    /// Builds the line item, panicking when it is invalid.
+    pub fn build(self) -> LineItem {
+        self.try_build().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_build(self) -> Result<LineItem, ValidationError> {
+        let product_id = self.product_id.ok_or(ValidationError::MissingField("product id"))?;
+        let product_name = to_title_case(&self.product_name.ok_or(ValidationError::MissingField("product name"))?);
+        let quantity = self.quantity.ok_or(ValidationError::MissingField("quantity"))?;
+        let price = match (self.unit_price, self.currency) {
+            (None, None) if self.total.is_none() => None,
+            (None, _) => return Err(ValidationError::MissingField("unit price")),
+            (Some(_), None) => return Err(ValidationError::MissingField("currency")),
+            (Some(unit_price), Some(currency)) => Some(price(unit_price, currency, &quantity, self.total)?),
+        };
+        Ok(LineItem { product_id, product_name, quantity, price })
+    }
+
+    fn is_started(&self) -> bool {
+        self.product_id.is_some() || self.product_name.is_some() || self.quantity.is_some()
+            || self.unit_price.is_some() || self.currency.is_some() || self.total.is_some()
+    }
+}
+
+fn price(unit_price: Decimal, currency: Currency, quantity: &Quantity, total: Option<Decimal>) -> Result<Price, ValidationError> {
+    if unit_price < Decimal::zero() {
+        return Err(ValidationError::NegativeUnitPrice(unit_price));
+    }
+    let computed = currency.round(unit_price.checked_mul(quantity.quantity)
+        .ok_or(ValidationError::PriceOverflow { unit_price, quantity: quantity.quantity })?);
+    match total {
+        Some(total) if total != computed => Err(ValidationError::TotalMismatch { expected: computed, actual: total }),
+        _ => Ok(Price { unit_price, currency, total: computed }),
     }
 }
 
@@ -118,11 +363,28 @@ impl Quantity {
     pub fn unit(&self) -> &Unit {
         &self.unit
     }
+
+    /// Converts the quantity to a unit of the same dimension. Conversions are exact, except when the result has more
+    /// digits than `Decimal` can hold (e.g. from kilograms to pounds), in which case it is rounded, and fail when it
+    /// is beyond the range of `Decimal`.
+    pub fn convert_to(&self, unit: Unit) -> Result<Quantity, UnitError> {
+        if self.unit.dimension() != unit.dimension() {
+            return Err(UnitError::Incompatible(self.unit, unit));
+        }
+        let quantity = if self.unit == unit {
+            self.quantity
+        } else {
+            self.quantity.checked_mul(self.unit.factor())
+                .and_then(|quantity| quantity.checked_div(unit.factor()))
+                .ok_or(UnitError::Overflow(self.unit, unit))?
+                .normalize()
+        };
+        Ok(Quantity { quantity, unit })
+    }
 }
 
 impl QuantityBuilder {
     pub fn with_quantity(mut self, quantity: Decimal) -> Self {
-        assert!(quantity > Decimal::zero(), "quantity should be > 0");
         self.quantity = Some(quantity);
         self
     }
@@ -132,19 +394,127 @@ impl QuantityBuilder {
         self
     }
 
+    /// Builds the quantity, panicking when it is invalid.
     pub fn build(self) -> Quantity {
-        Quantity {
-            quantity: self.quantity.expect("missing quantity"),
-            unit: self.unit.expect("missing unit"),
+        self.try_build().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_build(self) -> Result<Quantity, ValidationError> {
+        let quantity = self.quantity.ok_or(ValidationError::MissingField("quantity"))?;
+        if quantity <= Decimal::zero() {
+            return Err(ValidationError::NonPositiveQuantity(quantity));
         }
+        Ok(Quantity {
+            quantity,
+            unit: self.unit.ok_or(ValidationError::MissingField("unit"))?,
+        })
     }
 }
 
+/// Reason an order or a quantity could not be built.
 #[derive(Debug, Eq, PartialEq)]
+pub enum ValidationError {
+    MissingField(&'static str),
+    NonPositiveId,
+    NonPositiveQuantity(Decimal),
+    NegativeUnitPrice(Decimal),
+    InvalidCurrency(String),
+    TotalMismatch { expected: Decimal, actual: Decimal },
+    PriceOverflow { unit_price: Decimal, quantity: Decimal },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::MissingField(field) => write!(formatter, "missing {}", field),
+            ValidationError::NonPositiveId => write!(formatter, "id should be > 0"),
+            ValidationError::NonPositiveQuantity(quantity) => write!(formatter, "quantity should be > 0, got {}", quantity),
+            ValidationError::NegativeUnitPrice(unit_price) => write!(formatter, "unit price should be >= 0, got {}", unit_price),
+            ValidationError::InvalidCurrency(code) => write!(formatter, "invalid currency code {}", code),
+            ValidationError::TotalMismatch { expected, actual } =>
+                write!(formatter, "total should be {} (unit price × quantity), got {}", expected, actual),
+            ValidationError::PriceOverflow { unit_price, quantity } =>
+                write!(formatter, "total of unit price {} × quantity {} is out of range", unit_price, quantity),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Unit {
     KG,
+    G,
+    LB,
+    OZ,
+    L,
+    ML,
+    EACH,
 }
 
+#[derive(Debug, Eq, PartialEq)]
+pub enum Dimension {
+    Mass,
+    Volume,
+    Count,
+}
+
+impl Unit {
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Unit::KG | Unit::G | Unit::LB | Unit::OZ => Dimension::Mass,
+            Unit::L | Unit::ML => Dimension::Volume,
+            Unit::EACH => Dimension::Count,
+        }
+    }
+
+    /// Size of the unit in kilograms, litres or pieces, depending on its dimension.
+    fn factor(&self) -> Decimal {
+        match self {
+            Unit::KG | Unit::L | Unit::EACH => Decimal::new(1, 0),
+            Unit::G | Unit::ML => Decimal::new(1, 3),
+            Unit::LB => Decimal::new(45359237, 8),
+            Unit::OZ => Decimal::new(28349523125, 12),
+        }
+    }
+}
+
+impl FromStr for Unit {
+    type Err = UnitError;
+
+    fn from_str(unit: &str) -> Result<Self, Self::Err> {
+        match unit.to_lowercase().as_str() {
+            "kg" => Ok(Unit::KG),
+            "g" => Ok(Unit::G),
+            "lb" => Ok(Unit::LB),
+            "oz" => Ok(Unit::OZ),
+            "l" => Ok(Unit::L),
+            "ml" => Ok(Unit::ML),
+            "each" => Ok(Unit::EACH),
+            _ => Err(UnitError::Unknown(unit.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum UnitError {
+    Unknown(String),
+    Incompatible(Unit, Unit),
+    Overflow(Unit, Unit),
+}
+
+impl fmt::Display for UnitError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnitError::Unknown(unit) => write!(formatter, "unknown unit {}", unit),
+            UnitError::Incompatible(from, to) => write!(formatter, "cannot convert {:?} to {:?}", from, to),
+            UnitError::Overflow(from, to) => write!(formatter, "converting {:?} to {:?} is out of range", from, to),
+        }
+    }
+}
+
+impl Error for UnitError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -231,6 +601,130 @@ mod tests {
             .build();
     }
 
+    #[test]
+    fn order_builder_should_tell_validation_error() {
+        let builder = || Order::builder()
+            .with_date(date())
+            .with_product_id("product-id".to_string())
+            .with_product_name("product-name".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1, 0)).build());
+
+        assert_eq!(builder().try_build(), Err(ValidationError::MissingField("id")));
+        assert_eq!(builder().with_id(0).try_build(), Err(ValidationError::NonPositiveId));
+        assert_eq!(builder().with_id(1).try_build().unwrap().id(), 1);
+    }
+
+    #[test]
+    fn quantity_builder_should_tell_validation_error() {
+        assert_eq!(Quantity::builder().try_build(), Err(ValidationError::MissingField("quantity")));
+        assert_eq!(Quantity::builder().with_quantity(Decimal::new(-1, 0)).try_build(),
+                   Err(ValidationError::NonPositiveQuantity(Decimal::new(-1, 0))));
+        assert_eq!(ValidationError::NonPositiveQuantity(Decimal::new(-1, 0)).to_string(), "quantity should be > 0, got -1");
+    }
+
+    #[test]
+    fn should_compute_total_rounded_to_currency() {
+        let order = |currency: &str| Order::builder()
+            .with_id(1)
+            .with_date(date())
+            .with_product_id("product-id".to_string())
+            .with_product_name("product-name".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(3, 0)).build())
+            .with_unit_price(Decimal::new(1005, 3))
+            .with_currency(currency.parse().unwrap())
+            .build();
+
+        assert_eq!(order("usd").line_items()[0].price().unwrap().total().to_string(), "3.02");
+        assert_eq!(order("JPY").line_items()[0].price().unwrap().total().to_string(), "3");
+        assert_eq!(order("KWD").line_items()[0].price().unwrap().total().to_string(), "3.015");
+    }
+
+    #[test]
+    fn should_check_total() {
+        let builder = || Order::builder()
+            .with_id(1)
+            .with_date(date())
+            .with_product_id("product-id".to_string())
+            .with_product_name("product-name".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(2, 0)).build())
+            .with_unit_price(Decimal::new(150, 2))
+            .with_currency("EUR".parse().unwrap());
+
+        assert_eq!(builder().with_total(Decimal::new(3, 0)).try_build().unwrap().line_items()[0].price().unwrap().total(), &Decimal::new(300, 2));
+        assert_eq!(builder().with_total(Decimal::new(301, 2)).try_build(),
+                   Err(ValidationError::TotalMismatch { expected: Decimal::new(300, 2), actual: Decimal::new(301, 2) }));
+        assert_eq!(builder().with_unit_price(Decimal::new(-1, 0)).try_build(), Err(ValidationError::NegativeUnitPrice(Decimal::new(-1, 0))));
+        assert_eq!(builder().with_unit_price(Decimal::MAX).try_build(),
+                   Err(ValidationError::PriceOverflow { unit_price: Decimal::MAX, quantity: Decimal::new(2, 0) }));
+    }
+
+    #[test]
+    fn should_require_currency_with_unit_price() {
+        let builder = Order::builder()
+            .with_id(1)
+            .with_date(date())
+            .with_product_id("product-id".to_string())
+            .with_product_name("product-name".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(2, 0)).build());
+
+        assert_eq!(builder.with_unit_price(Decimal::new(1, 0)).try_build(), Err(ValidationError::MissingField("currency")));
+        assert_eq!("EURO".parse::<Currency>(), Err(ValidationError::InvalidCurrency("EURO".to_string())));
+    }
+
+    #[test]
+    fn should_convert_unit_price_with_quantity() {
+        let order = Order::builder()
+            .with_id(1)
+            .with_date(date())
+            .with_product_id("product-id".to_string())
+            .with_product_name("product-name".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(500, 0)).with_unit(Unit::G).build())
+            .with_unit_price(Decimal::new(2, 2))
+            .with_currency("EUR".parse().unwrap())
+            .build()
+            .normalise_to(Unit::KG)
+            .unwrap();
+
+        assert_eq!(order.line_items()[0].price().unwrap().unit_price(), &Decimal::new(20, 0));
+        assert_eq!(order.line_items()[0].price().unwrap().total(), &Decimal::new(1000, 2));
+    }
+
+    #[test]
+    fn should_build_order_with_line_items() {
+        let order = Order::builder()
+            .with_id(1)
+            .with_date(date())
+            .with_line_item(line_item("first"))
+            .with_line_item(line_item("second"))
+            .build();
+
+        let product_names: Vec<&str> = order.line_items().iter().map(LineItem::product_name).collect();
+        assert_eq!(product_names, vec!["First", "Second"]);
+    }
+
+    #[test]
+    fn order_builder_requires_line_item() {
+        let result = Order::builder().with_id(1).with_date(date()).try_build();
+
+        assert_eq!(result, Err(ValidationError::MissingField("product id")));
+    }
+
+    #[test]
+    fn should_merge_orders_with_same_id_and_date() {
+        let order = |product_name: &str, date: NaiveDate| Order::builder()
+            .with_id(1)
+            .with_date(date)
+            .with_line_item(line_item(product_name))
+            .build();
+        let mut merged = order("first", date());
+
+        merged.merge(order("second", date())).unwrap();
+        let conflicting = merged.merge(order("third", NaiveDate::from_ymd_opt(2019, 8, 28).unwrap())).err().unwrap();
+
+        assert_eq!(merged.line_items().len(), 2);
+        assert_eq!(conflicting.line_items()[0].product_name(), "Third");
+    }
+
     #[test]
     fn should_title_case_product_name() {
         let order = Order::builder()
@@ -241,10 +735,65 @@ mod tests {
             .with_quantity(Quantity::builder().with_quantity(Decimal::new(1, 0)).build())
             .build();
 
-        assert_eq!(order.product_name, "Product Name");
+        assert_eq!(order.line_items[0].product_name, "Product Name");
+    }
+
+    #[test]
+    fn should_convert_between_compatible_units() {
+        let quantity = Quantity::builder().with_quantity(Decimal::new(2, 0)).with_unit(Unit::LB).build();
+
+        assert_eq!(quantity.convert_to(Unit::KG).unwrap().quantity(), &Decimal::new(90718474, 8));
+        assert_eq!(quantity.convert_to(Unit::OZ).unwrap().quantity(), &Decimal::new(32, 0));
+        assert_eq!(quantity.convert_to(Unit::G).unwrap().quantity(), &Decimal::new(90718474, 5));
+        assert_eq!(quantity.convert_to(Unit::G).unwrap().unit(), &Unit::G);
+    }
+
+    #[test]
+    fn should_convert_to_same_unit() {
+        let quantity = Quantity::builder().with_quantity(Decimal::new(1250, 3)).with_unit(Unit::L).build();
+
+        assert_eq!(quantity.convert_to(Unit::L).unwrap(), quantity);
+        assert_eq!(quantity.convert_to(Unit::ML).unwrap().quantity(), &Decimal::new(1250, 0));
+    }
+
+    #[test]
+    fn should_not_convert_between_incompatible_units() {
+        let quantity = Quantity::builder().with_quantity(Decimal::new(1, 0)).with_unit(Unit::EACH).build();
+
+        assert_eq!(quantity.convert_to(Unit::KG), Err(UnitError::Incompatible(Unit::EACH, Unit::KG)));
+    }
+
+    #[test]
+    fn should_not_convert_beyond_decimal_range() {
+        let quantity = Quantity::builder().with_quantity(Decimal::MAX).with_unit(Unit::KG).build();
+        let line_item = LineItem::builder()
+            .with_product_id("product-id".to_string())
+            .with_product_name("product-name".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1, 3)).with_unit(Unit::G).build())
+            .with_unit_price(Decimal::MAX / Decimal::new(100, 0))
+            .with_currency("EUR".parse().unwrap())
+            .build();
+
+        assert_eq!(quantity.convert_to(Unit::G), Err(UnitError::Overflow(Unit::KG, Unit::G)));
+        assert_eq!(line_item.normalise_to(Unit::KG).err().unwrap().to_string(), "converting G to KG is out of range");
+    }
+
+    #[test]
+    fn should_parse_unit() {
+        assert_eq!("Kg".parse::<Unit>(), Ok(Unit::KG));
+        assert_eq!("each".parse::<Unit>(), Ok(Unit::EACH));
+        assert_eq!("stone".parse::<Unit>(), Err(UnitError::Unknown("stone".to_string())));
+    }
+
+    fn line_item(product_name: &str) -> LineItem {
+        LineItem::builder()
+            .with_product_id("product-id".to_string())
+            .with_product_name(product_name.to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1, 0)).build())
+            .build()
     }
 
     fn date() -> NaiveDate {
-        NaiveDate::from_ymd(2019, 8, 22)
+        NaiveDate::from_ymd_opt(2019, 8, 22).unwrap()
     }
 }
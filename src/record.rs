@@ -1,20 +1,165 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 
+use crate::value::Value;
+
 pub trait Record {
     fn id(&self) -> u64;
 
+    /// Value of a column as text, whatever its type in the source; nulls and bytes have no text.
     fn value_for(&self, name: &str) -> Option<&String>;
+
+    /// Value of a column with the type it has in the source, to be coerced to the expected type on demand. Records
+    /// without types only have strings, copied from their text.
+    fn typed_value_for(&self, name: &str) -> Option<Cow<'_, Value>> {
+        self.value_for(name).map(|value| Cow::Owned(Value::String(value.to_owned())))
+    }
+
+    /// Raw values of the record by column name, in source order when the source has one.
+    fn values(&self) -> Vec<(&str, &str)>;
+
+    fn provenance(&self) -> &Provenance;
+}
+
+/// Lends a record to a transformer which does not need to own it.
+impl<R: Record + ?Sized> Record for &R {
+    fn id(&self) -> u64 {
+        (**self).id()
+    }
+
+    fn value_for(&self, name: &str) -> Option<&String> {
+        (**self).value_for(name)
+    }
+
+    fn typed_value_for(&self, name: &str) -> Option<Cow<'_, Value>> {
+        (**self).typed_value_for(name)
+    }
+
+    fn values(&self) -> Vec<(&str, &str)> {
+        (**self).values()
+    }
+
+    fn provenance(&self) -> &Provenance {
+        (**self).provenance()
+    }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+/// Where a record was extracted from, as far as its extractor knows.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Provenance {
+    source: Option<String>,
+    line: Option<u64>,
+    byte: Option<u64>,
+    raw_text: Option<String>,
+}
+
+impl Provenance {
+    /// Names the source the record comes from, typically its file.
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = Some(source.to_string());
+        self
+    }
+
+    /// Sets the line (from 1) the record starts at in its source.
+    pub fn with_line(mut self, line: u64) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    /// Sets the offset (from 0) of the first byte of the record in its source, once transcoded to UTF-8.
+    pub fn with_byte(mut self, byte: u64) -> Self {
+        self.byte = Some(byte);
+        self
+    }
+
+    /// Sets the text the record was parsed from, without its line terminator.
+    pub fn with_raw_text(mut self, raw_text: &str) -> Self {
+        self.raw_text = Some(raw_text.to_string());
+        self
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub fn line(&self) -> Option<u64> {
+        self.line
+    }
+
+    pub fn byte(&self) -> Option<u64> {
+        self.byte
+    }
+
+    pub fn raw_text(&self) -> Option<&str> {
+        self.raw_text.as_deref()
+    }
+}
+
+/// Record keeping its values as text, along with the typed values of the ones which are not strings in the source.
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct MapRecord {
     id: u64,
-    map: HashMap<String, String>,
+    values: Vec<(String, String)>,
+    typed_values: Vec<(String, Value)>,
+    positions: HashMap<String, usize>,
+    typed_positions: HashMap<String, usize>,
+    provenance: Provenance,
 }
 
 impl MapRecord {
+    /// Record with the given values, ordered by column name.
     pub fn new(id: u64, map: HashMap<String, String>) -> Self {
-        MapRecord { id, map }
+        let mut values: Vec<(String, String)> = map.into_iter().collect();
+        values.sort();
+        MapRecord::indexed(id, values, Vec::new())
+    }
+
+    /// Record with the given values, kept in the given order.
+    pub fn with_columns(id: u64, values: Vec<(String, String)>) -> Self {
+        MapRecord::indexed(id, values, Vec::new())
+    }
+
+    /// Record with the given typed values, kept in the given order.
+    pub fn with_typed_columns(id: u64, columns: Vec<(String, Value)>) -> Self {
+        let mut values = Vec::new();
+        let mut typed_values = Vec::new();
+        for (name, value) in columns {
+            match value {
+                Value::String(value) => values.push((name, value)),
+                Value::Null | Value::Bytes(_) => typed_values.push((name, value)),
+                value => {
+                    values.push((name.clone(), value.to_string()));
+                    typed_values.push((name, value));
+                }
+            }
+        }
+        MapRecord::indexed(id, values, typed_values)
+    }
+
+    fn indexed(id: u64, values: Vec<(String, String)>, typed_values: Vec<(String, Value)>) -> Self {
+        let positions = MapRecord::positions(&values);
+        let typed_positions = MapRecord::positions(&typed_values);
+        MapRecord { id, values, typed_values, positions, typed_positions, provenance: Provenance::default() }
+    }
+
+    /// Position of each column, the first one winning when a column is repeated.
+    fn positions<T>(columns: &[(String, T)]) -> HashMap<String, usize> {
+        let mut positions = HashMap::with_capacity(columns.len());
+        for (position, (name, _)) in columns.iter().enumerate() {
+            positions.entry(name.clone()).or_insert(position);
+        }
+        positions
+    }
+
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Gives the record another id, e.g. to keep ids unique when records of several sources are chained.
+    pub(crate) fn renumbered(mut self, id: u64) -> Self {
+        self.id = id;
+        self
     }
 }
 
@@ -24,6 +169,22 @@ impl Record for MapRecord {
     }
 
     fn value_for(&self, name: &str) -> Option<&String> {
-        self.map.get(name)
+        self.positions.get(name).map(|&position| &self.values[position].1)
+    }
+
+    fn typed_value_for(&self, name: &str) -> Option<Cow<'_, Value>> {
+        self.typed_positions.get(name)
+            .map(|&position| Cow::Borrowed(&self.typed_values[position].1))
+            .or_else(|| self.value_for(name).map(|value| Cow::Owned(Value::String(value.to_owned()))))
+    }
+
+    fn values(&self) -> Vec<(&str, &str)> {
+        self.values.iter()
+            .map(|(column, value)| (column.as_str(), value.as_str()))
+            .collect()
+    }
+
+    fn provenance(&self) -> &Provenance {
+        &self.provenance
     }
 }
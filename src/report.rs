@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::loader::OrderErrorKind;
+use crate::transformer::{FieldError, RecordErrorKind};
+
+/// Summary of a single `Engine` run: per-stage counters and timings, and discards grouped by error message and by
+/// error kind.
+#[derive(Debug, Default)]
+pub struct RunReport {
+    extracted: u64,
+    skipped: u64,
+    transformed: u64,
+    discarded_records: u64,
+    failed_extractions: u64,
+    loaded: u64,
+    discarded_orders: u64,
+    extraction_time: Duration,
+    transformation_time: Duration,
+    loading_time: Duration,
+    elapsed: Duration,
+    record_discards: HashMap<String, u64>,
+    order_discards: HashMap<String, u64>,
+    extraction_discards: HashMap<String, u64>,
+    record_error_kinds: HashMap<RecordErrorKind, u64>,
+    order_error_kinds: HashMap<OrderErrorKind, u64>,
+}
+
+impl RunReport {
+    pub(crate) fn new() -> Self {
+        RunReport::default()
+    }
+
+    /// Number of records handed over to the transformer.
+    pub fn extracted(&self) -> u64 {
+        self.extracted
+    }
+
+    /// Number of records skipped as already committed by a previous run.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    pub fn transformed(&self) -> u64 {
+        self.transformed
+    }
+
+    pub fn discarded_records(&self) -> u64 {
+        self.discarded_records
+    }
+
+    /// Number of records which could not be extracted; they are counted as extracted too.
+    pub fn failed_extractions(&self) -> u64 {
+        self.failed_extractions
+    }
+
+    pub fn loaded(&self) -> u64 {
+        self.loaded
+    }
+
+    pub fn discarded_orders(&self) -> u64 {
+        self.discarded_orders
+    }
+
+    pub fn extraction_time(&self) -> Duration {
+        self.extraction_time
+    }
+
+    /// Time spent transforming records; with several workers it adds up the time of all of them.
+    pub fn transformation_time(&self) -> Duration {
+        self.transformation_time
+    }
+
+    pub fn loading_time(&self) -> Duration {
+        self.loading_time
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Number of errors of the records discarded by the transformer, per error message; a record with several errors
+    /// is counted under each of them.
+    pub fn record_discards(&self) -> &HashMap<String, u64> {
+        &self.record_discards
+    }
+
+    /// Number of orders discarded by the loader, per error message.
+    pub fn order_discards(&self) -> &HashMap<String, u64> {
+        &self.order_discards
+    }
+
+    /// Number of records which could not be extracted, per error category.
+    pub fn extraction_discards(&self) -> &HashMap<String, u64> {
+        &self.extraction_discards
+    }
+
+    /// Number of errors of the records discarded by the transformer, per error kind.
+    pub fn record_error_kinds(&self) -> &HashMap<RecordErrorKind, u64> {
+        &self.record_error_kinds
+    }
+
+    /// Number of orders discarded by the loader, per error kind.
+    pub fn order_error_kinds(&self) -> &HashMap<OrderErrorKind, u64> {
+        &self.order_error_kinds
+    }
+
+    pub(crate) fn extraction(&mut self, extracted: u64, skipped: u64, time: Duration) {
+        self.extracted += extracted;
+        self.skipped += skipped;
+        self.extraction_time += time;
+    }
+
+    pub(crate) fn failed_extraction(&mut self, category: &str) {
+        self.failed_extractions += 1;
+        *self.extraction_discards.entry(category.to_string()).or_insert(0) += 1;
+    }
+
+    pub(crate) fn transformation(&mut self, time: Duration) {
+        self.transformation_time += time;
+    }
+
+    pub(crate) fn transformed_record(&mut self) {
+        self.transformed += 1;
+    }
+
+    /// Counts a discarded record once, and each of its errors under its message and its kind.
+    pub(crate) fn discarded_record(&mut self, errors: &[FieldError]) {
+        self.discarded_records += 1;
+        for error in errors {
+            *self.record_discards.entry(error.message().to_string()).or_insert(0) += 1;
+            *self.record_error_kinds.entry(error.kind()).or_insert(0) += 1;
+        }
+    }
+
+    pub(crate) fn loading(&mut self, time: Duration) {
+        self.loading_time += time;
+    }
+
+    pub(crate) fn loaded_order(&mut self) {
+        self.loaded += 1;
+    }
+
+    pub(crate) fn discarded_order(&mut self, kind: OrderErrorKind, error_message: &str) {
+        self.discarded_orders += 1;
+        *self.order_discards.entry(error_message.to_string()).or_insert(0) += 1;
+        *self.order_error_kinds.entry(kind).or_insert(0) += 1;
+    }
+
+    /// Counts an order reported as loaded, and then rolled back, as discarded instead.
+    pub(crate) fn rolled_back_order(&mut self, kind: OrderErrorKind, error_message: &str) {
+        self.loaded = self.loaded.saturating_sub(1);
+        self.discarded_order(kind, error_message);
+    }
+
+    pub(crate) fn finished(&mut self, elapsed: Duration) {
+        self.elapsed = elapsed;
+    }
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(formatter, "extracted: {} (skipped: {}, failed: {}) in {:?}", self.extracted, self.skipped, self.failed_extractions, self.extraction_time)?;
+        writeln!(formatter, "transformed: {} (discarded: {}) in {:?}", self.transformed, self.discarded_records, self.transformation_time)?;
+        writeln!(formatter, "loaded: {} (discarded: {}) in {:?}", self.loaded, self.discarded_orders, self.loading_time)?;
+        write!(formatter, "elapsed: {:?}", self.elapsed)?;
+        write_discards(formatter, "failed extractions", &self.extraction_discards)?;
+        write_discards(formatter, "discarded records", &self.record_discards)?;
+        write_discards(formatter, "discarded orders", &self.order_discards)
+    }
+}
+
+fn write_discards(formatter: &mut fmt::Formatter, title: &str, discards: &HashMap<String, u64>) -> fmt::Result {
+    if discards.is_empty() {
+        return Ok(());
+    }
+    write!(formatter, "\n{}:", title)?;
+    let mut discards: Vec<(&String, &u64)> = discards.iter().collect();
+    discards.sort_by(|(message, count), (other_message, other_count)| other_count.cmp(count).then(message.cmp(other_message)));
+    discards.iter().try_for_each(|(message, count)| write!(formatter, "\n  {}: {}", message, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_group_discards_by_error_message() {
+        let mut report = RunReport::new();
+
+        report.discarded_record(&[error("date", RecordErrorKind::Malformed, "Invalid date.")]);
+        report.discarded_record(&[error("quantity", RecordErrorKind::Invalid, "Invalid count.")]);
+        report.discarded_record(&[error("date", RecordErrorKind::Malformed, "Invalid date."),
+            error("quantity", RecordErrorKind::Invalid, "Invalid count.")]);
+        report.discarded_order(OrderErrorKind::Io, "disk full");
+
+        assert_eq!(report.discarded_records(), 3);
+        assert_eq!(report.record_discards().get("Invalid date."), Some(&2));
+        assert_eq!(report.record_discards().get("Invalid count."), Some(&2));
+        assert_eq!(report.discarded_orders(), 1);
+        assert_eq!(report.order_discards().get("disk full"), Some(&1));
+    }
+
+    #[test]
+    fn should_group_discards_by_error_kind() {
+        let mut report = RunReport::new();
+
+        report.discarded_record(&[error("date", RecordErrorKind::Missing, "Missing date."),
+            error("quantity", RecordErrorKind::Malformed, "Invalid count.")]);
+        report.discarded_record(&[error("product_id", RecordErrorKind::Missing, "Missing product id.")]);
+        report.discarded_order(OrderErrorKind::Duplicate, "Order already exists.");
+        report.discarded_order(OrderErrorKind::Duplicate, "Order already exists.");
+
+        assert_eq!(report.record_error_kinds().get(&RecordErrorKind::Missing), Some(&2));
+        assert_eq!(report.record_error_kinds().get(&RecordErrorKind::Malformed), Some(&1));
+        assert_eq!(report.record_error_kinds().get(&RecordErrorKind::Invalid), None);
+        assert_eq!(report.order_error_kinds().get(&OrderErrorKind::Duplicate), Some(&2));
+    }
+
+    #[test]
+    fn should_count_rolled_back_orders_as_discarded() {
+        let mut report = RunReport::new();
+        report.loaded_order();
+        report.loaded_order();
+
+        report.rolled_back_order(OrderErrorKind::Constraint, "FOREIGN KEY constraint failed");
+
+        assert_eq!(report.loaded(), 1);
+        assert_eq!(report.discarded_orders(), 1);
+        assert_eq!(report.order_error_kinds().get(&OrderErrorKind::Constraint), Some(&1));
+    }
+
+    #[test]
+    fn should_display_most_frequent_discards_first() {
+        let mut report = RunReport::new();
+        report.extraction(4, 1, Duration::from_millis(1));
+        report.transformed_record();
+        report.loaded_order();
+        report.discarded_record(&[error("quantity", RecordErrorKind::Invalid, "Invalid count.")]);
+        report.discarded_record(&[error("date", RecordErrorKind::Malformed, "Invalid date.")]);
+        report.discarded_record(&[error("date", RecordErrorKind::Malformed, "Invalid date.")]);
+
+        assert_eq!(report.to_string(), "extracted: 4 (skipped: 1, failed: 0) in 1ms\n\
+                                         transformed: 1 (discarded: 3) in 0ns\n\
+                                         loaded: 1 (discarded: 0) in 0ns\n\
+                                         elapsed: 0ns\n\
+                                         discarded records:\n  \
+                                         Invalid date.: 2\n  \
+                                         Invalid count.: 1");
+    }
+
+    fn error(field: &str, kind: RecordErrorKind, message: &str) -> FieldError {
+        FieldError::new(field, kind, message.to_string())
+    }
+}
@@ -1,3 +1,4 @@
+use crate::extractor::ExtractorError;
 use crate::loader::DiscardedOrder;
 use crate::transformer::DiscardedRecord;
 
@@ -5,4 +6,8 @@ pub trait Reporter {
     fn report_record(&self, discarded_record: DiscardedRecord);
 
     fn report_order(&self, discarded_order: DiscardedOrder);
+
+    /// Reports a record which could not be extracted, and so was neither transformed nor loaded; ignored unless
+    /// overridden, the failure being counted in the run report anyway.
+    fn report_extraction(&self, _extractor_error: ExtractorError) {}
 }
\ No newline at end of file
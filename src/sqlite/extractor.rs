@@ -0,0 +1,243 @@
+use std::any::Any;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use rust_decimal::Decimal;
+use rusqlite::Connection;
+use rusqlite::types::ValueRef;
+
+use crate::extractor::{Extractor, ExtractorError};
+use crate::record::{MapRecord, Record};
+use crate::value::Value;
+
+const BUFFERED_ROWS: usize = 256;
+
+/// Where the id of each extracted record comes from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RecordId {
+    /// The number of the row in the query results, starting from 1.
+    RowNumber,
+    /// The value of the given column, which has to be a positive integer.
+    Column(String),
+}
+
+/// Extracts the rows returned by a query as records keyed by column name, each value typed after its SQLite storage
+/// class: `NULL`s, integers, reals (as decimals, or as text beyond their range), text and blobs. `NULL`s and blobs
+/// have no text.
+///
+/// The query runs on a separate thread owning the connection, which streams the rows through a bounded channel. The
+/// thread is joined once the channel closes, a panic ending the extraction with a failed one.
+pub struct SqlExtractor {
+    receiver: Receiver<Result<MapRecord, ExtractorError>>,
+    thread: Option<JoinHandle<()>>,
+    position: u64,
+}
+
+impl SqlExtractor {
+    pub fn query(connection: Connection, query: &str, id: RecordId) -> Result<Self, Box<dyn Error>> {
+        let (prepared_sender, prepared_receiver) = mpsc::sync_channel(1);
+        let (sender, receiver) = mpsc::sync_channel(BUFFERED_ROWS);
+        let query = query.to_string();
+        let thread = thread::spawn(move || stream(connection, &query, &id, prepared_sender, sender));
+
+        match prepared_receiver.recv() {
+            Ok(Ok(())) => Ok(SqlExtractor { receiver, thread: Some(thread), position: 0 }),
+            Ok(Err(message)) => Err(Box::new(ExtractorError::new(&message))),
+            Err(e) => match thread.join() {
+                Err(cause) => Err(Box::new(ExtractorError::new(&panic_message(cause)))),
+                Ok(()) => Err(Box::new(e)),
+            },
+        }
+    }
+}
+
+impl Extractor<MapRecord> for SqlExtractor {}
+
+impl Iterator for SqlExtractor {
+    type Item = Result<MapRecord, ExtractorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.receiver.recv() {
+            Ok(record) => {
+                self.position += 1;
+                Some(record)
+            }
+            Err(_) => match self.thread.take()?.join() {
+                Ok(()) => None,
+                Err(cause) => {
+                    self.position += 1;
+                    Some(Err(ExtractorError::at(self.position, &panic_message(cause))))
+                }
+            },
+        }
+    }
+}
+
+fn panic_message(cause: Box<dyn Any + Send>) -> String {
+    let message = cause.downcast_ref::<&str>().copied()
+        .or_else(|| cause.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    format!("extraction thread panicked: {}", message)
+}
+
+fn stream(connection: Connection,
+          query: &str,
+          id: &RecordId,
+          prepared_sender: SyncSender<Result<(), String>>,
+          sender: SyncSender<Result<MapRecord, ExtractorError>>) {
+    let mut statement = match connection.prepare(query) {
+        Ok(statement) => statement,
+        Err(e) => {
+            let _ = prepared_sender.send(Err(e.to_string()));
+            return;
+        }
+    };
+    let columns: Vec<String> = statement.column_names().iter().map(|name| name.to_string()).collect();
+    if let RecordId::Column(name) = id {
+        if !columns.contains(name) {
+            let _ = prepared_sender.send(Err(format!("missing id column {}", name)));
+            return;
+        }
+    }
+    let mut rows = match statement.query([]) {
+        Ok(rows) => rows,
+        Err(e) => {
+            let _ = prepared_sender.send(Err(e.to_string()));
+            return;
+        }
+    };
+    let _ = prepared_sender.send(Ok(()));
+
+    let mut row_number = 0;
+    loop {
+        row_number += 1;
+        let (record, failed) = match rows.next() {
+            Ok(Some(row)) => {
+                let values: Vec<(String, Value)> = columns.iter().enumerate()
+                    .filter_map(|(index, name)| row.get_ref(index).ok()
+                        .map(|value| (name.to_owned(), to_value(value))))
+                    .collect();
+                (to_record(row_number, id, values), false)
+            }
+            Ok(None) => return,
+            Err(e) => (Err(ExtractorError::at(row_number, &e.to_string())), true),
+        };
+        if sender.send(record).is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// Reals are decimals with the digits of their shortest representation, or strings beyond the range of decimals.
+fn to_value(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(value) => Value::Int(value),
+        ValueRef::Real(value) => Decimal::from_str(&value.to_string()).map_or(Value::String(value.to_string()), Value::Decimal),
+        ValueRef::Text(value) => Value::String(String::from_utf8_lossy(value).into_owned()),
+        ValueRef::Blob(value) => Value::Bytes(value.to_vec()),
+    }
+}
+
+/// Values are ordered by column name.
+fn to_record(row_number: u64, id: &RecordId, mut values: Vec<(String, Value)>) -> Result<MapRecord, ExtractorError> {
+    values.sort_by(|(name, _), (other_name, _)| name.cmp(other_name));
+    let record = MapRecord::with_typed_columns(row_number, values);
+    let id = match id {
+        RecordId::RowNumber => return Ok(record),
+        RecordId::Column(name) => match record.typed_value_for(name).and_then(|value| value.to_uint().ok()).filter(|id| id > &0) {
+            Some(id) => id,
+            None => return Err(ExtractorError::at(row_number, &format!("invalid id in column {}", name))),
+        },
+    };
+    Ok(record.renumbered(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record::Record;
+
+    use super::*;
+
+    #[test]
+    fn should_extract() {
+        let extracted_records: Vec<MapRecord> = SqlExtractor::query(connection(), "SELECT * FROM products ORDER BY number", RecordId::RowNumber).unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        let first_expected_record = MapRecord::with_typed_columns(1, vec![
+            ("name".to_string(), Value::from("Nuts")),
+            ("number".to_string(), Value::Int(7)),
+            ("picture".to_string(), Value::Bytes(vec![0xca, 0xfe])),
+            ("price".to_string(), Value::Decimal(Decimal::new(125, 1))),
+        ]);
+        let second_expected_record = MapRecord::with_typed_columns(2, vec![
+            ("name".to_string(), Value::from("Jam")),
+            ("number".to_string(), Value::Int(9)),
+            ("picture".to_string(), Value::Null),
+            ("price".to_string(), Value::Null),
+        ]);
+
+        assert_eq!(extracted_records, vec![first_expected_record, second_expected_record]);
+        assert_eq!(extracted_records[0].value_for("price"), Some(&"12.5".to_string()));
+        assert_eq!(extracted_records[0].value_for("picture"), None);
+    }
+
+    #[test]
+    fn should_take_id_from_column() {
+        let ids: Vec<u64> = SqlExtractor::query(connection(), "SELECT number, name FROM products ORDER BY number", RecordId::Column("number".to_string())).unwrap()
+            .map(|record| record.unwrap().id())
+            .collect();
+
+        assert_eq!(ids, vec![7, 9]);
+    }
+
+    #[test]
+    fn should_fail_on_invalid_id() {
+        let extracted_records: Vec<Result<MapRecord, ExtractorError>> = SqlExtractor::query(connection(), "SELECT name FROM products ORDER BY number", RecordId::Column("name".to_string())).unwrap()
+            .collect();
+
+        assert_eq!(extracted_records.len(), 2);
+        assert_eq!(extracted_records[0].as_ref().err().unwrap().to_string(), "invalid id in column name");
+        assert_eq!(extracted_records[1].as_ref().err().unwrap().position(), 2);
+    }
+
+    #[test]
+    fn should_fail_on_missing_id_column() {
+        let result = SqlExtractor::query(connection(), "SELECT name FROM products", RecordId::Column("id".to_string()));
+
+        assert_eq!(result.err().unwrap().to_string(), "missing id column id");
+    }
+
+    #[test]
+    fn should_fail_when_extraction_thread_panics() {
+        let (sender, receiver) = mpsc::sync_channel(BUFFERED_ROWS);
+        let thread = thread::spawn(move || {
+            sender.send(Ok(MapRecord::with_columns(1, vec![]))).unwrap();
+            panic!("row decoding failed");
+        });
+        let extractor = SqlExtractor { receiver, thread: Some(thread), position: 0 };
+
+        let extracted_records: Vec<Result<MapRecord, ExtractorError>> = extractor.collect();
+
+        assert_eq!(extracted_records.len(), 2);
+        let failure = extracted_records[1].as_ref().err().unwrap();
+        assert_eq!(failure.position(), 2);
+        assert_eq!(failure.to_string(), "extraction thread panicked: row decoding failed");
+    }
+
+    #[test]
+    fn should_fail_on_invalid_query() {
+        assert!(SqlExtractor::query(connection(), "SELECT * FROM missing", RecordId::RowNumber).is_err());
+    }
+
+    fn connection() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch("CREATE TABLE products (number INTEGER, name TEXT, price REAL, picture BLOB);\n\
+                                  INSERT INTO products VALUES (9, 'Jam', NULL, NULL);\n\
+                                  INSERT INTO products VALUES (7, 'Nuts', 12.5, x'cafe');").unwrap();
+        connection
+    }
+}
@@ -0,0 +1,492 @@
+use std::convert::TryFrom;
+use std::error::Error;
+
+use rusqlite::{Connection, ErrorCode, params};
+
+use crate::loader::{DiscardedOrder, Loader, OrderErrorKind};
+use crate::order::Order;
+
+const CREATE_TABLES: &str = "CREATE TABLE IF NOT EXISTS orders (\
+                                 id INTEGER PRIMARY KEY, \
+                                 date TEXT NOT NULL);\
+                             CREATE TABLE IF NOT EXISTS order_lines (\
+                                 order_id INTEGER NOT NULL REFERENCES orders (id), \
+                                 line INTEGER NOT NULL, \
+                                 product_id TEXT NOT NULL, \
+                                 product_name TEXT NOT NULL, \
+                                 quantity TEXT NOT NULL, \
+                                 unit TEXT NOT NULL, \
+                                 unit_price TEXT, \
+                                 currency TEXT, \
+                                 total TEXT, \
+                                 PRIMARY KEY (order_id, line))";
+/// Moves the single line item of each order of the first layout, where orders held their product, to `order_lines`.
+const SPLIT_ORDERS: &str = "BEGIN;\
+                            INSERT INTO order_lines (order_id, line, product_id, product_name, quantity, unit) \
+                                SELECT id, 1, product_id, product_name, quantity, unit FROM orders;\
+                            ALTER TABLE orders DROP COLUMN product_id;\
+                            ALTER TABLE orders DROP COLUMN product_name;\
+                            ALTER TABLE orders DROP COLUMN quantity;\
+                            ALTER TABLE orders DROP COLUMN unit;\
+                            COMMIT";
+/// Adds the price of line items to the layout preceding them.
+const ADD_PRICES: &str = "ALTER TABLE order_lines ADD COLUMN unit_price TEXT;\
+                          ALTER TABLE order_lines ADD COLUMN currency TEXT;\
+                          ALTER TABLE order_lines ADD COLUMN total TEXT";
+const INSERT_ORDER: &str = "INSERT INTO orders (id, date) VALUES (?1, ?2)";
+const UPSERT: &str = " ON CONFLICT (id) DO UPDATE SET date = excluded.date";
+const SKIP: &str = " ON CONFLICT (id) DO NOTHING";
+const DELETE_LINES: &str = "DELETE FROM order_lines WHERE order_id = ?1";
+const INSERT_LINE: &str = "INSERT INTO order_lines (order_id, line, product_id, product_name, quantity, unit, unit_price, currency, total) \
+                           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)";
+
+const ORDER_EXISTS: &str = "Order already exists.";
+const ID_OUT_OF_RANGE: &str = "Order id exceeds the range of SQLite integers.";
+
+/// What to do with an order whose id has already been loaded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConflictPolicy {
+    /// Replaces the existing order.
+    Upsert,
+    /// Keeps the existing order and silently drops the new one.
+    Skip,
+    /// Keeps the existing order and discards the new one.
+    Reject,
+}
+
+/// Loads orders into the `orders` table and their line items, numbered from 1, into the `order_lines` table (both
+/// created when missing, or migrated when written by a previous version), committing them in batches of
+/// transactions. Replacing an order replaces all its line items; the price columns of line items without a price are
+/// left `NULL`. Orders whose id exceeds the range of SQLite integers (`i64::MAX`) are discarded.
+///
+/// Orders become durable only once their batch is committed, which flushing the loader forces. Each order is
+/// inserted within a savepoint, so that a failing order is rolled back alone, while a batch failing to commit rolls
+/// back all its orders.
+pub struct SqliteLoader {
+    connection: Connection,
+    policy: ConflictPolicy,
+    batch_size: usize,
+    /// Orders inserted in the current batch, loaded or not.
+    pending: usize,
+    /// Orders loaded in the current batch, to be discarded if it fails to commit.
+    batch: Vec<Order>,
+    rolled_back: Vec<DiscardedOrder>,
+}
+
+impl SqliteLoader {
+    pub fn to(connection: Connection) -> Result<Self, Box<dyn Error>> {
+        connection.execute_batch(CREATE_TABLES)?;
+        migrate(&connection)?;
+        Ok(SqliteLoader { connection, policy: ConflictPolicy::Reject, batch_size: 1000, pending: 0, batch: Vec::new(), rolled_back: Vec::new() })
+    }
+
+    pub fn with_conflict_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size should be > 0");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Inserts an order within a savepoint of the current batch, so that a failing order leaves nothing behind.
+    fn insert(&mut self, id: i64, order: &Order) -> Result<bool, rusqlite::Error> {
+        if self.pending == 0 {
+            self.connection.execute_batch("BEGIN")?;
+        }
+        self.pending += 1;
+        self.connection.execute_batch("SAVEPOINT loaded_order")?;
+        let changes = match self.write(id, order) {
+            Ok(changes) => changes,
+            Err(e) => {
+                let _ = self.connection.execute_batch("ROLLBACK TO loaded_order; RELEASE loaded_order");
+                return Err(e);
+            }
+        };
+        self.connection.execute_batch("RELEASE loaded_order")?;
+        if self.pending >= self.batch_size {
+            self.commit()?;
+        }
+        Ok(changes > 0)
+    }
+
+    fn write(&mut self, id: i64, order: &Order) -> Result<usize, rusqlite::Error> {
+        let statement = match self.policy {
+            ConflictPolicy::Upsert => format!("{}{}", INSERT_ORDER, UPSERT),
+            ConflictPolicy::Skip | ConflictPolicy::Reject => format!("{}{}", INSERT_ORDER, SKIP),
+        };
+        let changes = self.connection.prepare_cached(&statement)?.execute(params![
+            id,
+            order.date().to_string(),
+        ])?;
+        if changes > 0 {
+            self.connection.prepare_cached(DELETE_LINES)?.execute(params![id])?;
+            let mut insert_line = self.connection.prepare_cached(INSERT_LINE)?;
+            for (line, line_item) in order.line_items().iter().enumerate() {
+                let price = line_item.price();
+                insert_line.execute(params![
+                    id,
+                    line as i64 + 1,
+                    line_item.product_id(),
+                    line_item.product_name(),
+                    line_item.quantity().quantity().to_string(),
+                    format!("{:?}", line_item.quantity().unit()),
+                    price.map(|price| price.unit_price().to_string()),
+                    price.map(|price| price.currency().code()),
+                    price.map(|price| price.total().to_string()),
+                ])?;
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Commits the current batch; when the commit fails, the batch is rolled back and all its loaded orders are
+    /// discarded.
+    fn commit(&mut self) -> Result<(), rusqlite::Error> {
+        self.pending = 0;
+        let batch = std::mem::take(&mut self.batch);
+        if let Err(e) = self.connection.execute_batch("COMMIT") {
+            let _ = self.connection.execute_batch("ROLLBACK");
+            self.rolled_back.extend(batch.into_iter()
+                .map(|order| DiscardedOrder::new(order, kind_of(&e), e.to_string())));
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+impl Loader for SqliteLoader {
+    fn load(&mut self, order: Order) -> Result<(), DiscardedOrder> {
+        let id = match i64::try_from(order.id()) {
+            Ok(id) => id,
+            Err(_) => return Err(DiscardedOrder::new(order, OrderErrorKind::Encoding, ID_OUT_OF_RANGE.to_string())),
+        };
+        match self.insert(id, &order) {
+            Ok(false) if self.policy == ConflictPolicy::Reject =>
+                Err(DiscardedOrder::new(order, OrderErrorKind::Duplicate, ORDER_EXISTS.to_string())),
+            Ok(inserted) => {
+                if inserted && self.pending > 0 {
+                    self.batch.push(order);
+                }
+                Ok(())
+            }
+            Err(e) => Err(DiscardedOrder::new(order, kind_of(&e), e.to_string())),
+        }
+    }
+
+    /// Commits the current batch, however small.
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.pending > 0 {
+            // a failed commit is told through the rolled back orders
+            let _ = self.commit();
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()
+    }
+
+    fn rolled_back(&mut self) -> Vec<DiscardedOrder> {
+        std::mem::take(&mut self.rolled_back)
+    }
+
+    /// Rolls back the orders of the current batch; the ones of already committed batches are kept.
+    fn abort(&mut self) {
+        if self.pending > 0 {
+            self.pending = 0;
+            self.batch.clear();
+            let _ = self.connection.execute_batch("ROLLBACK");
+        }
+    }
+}
+
+/// Brings tables written by a previous version to the current layout.
+fn migrate(connection: &Connection) -> rusqlite::Result<()> {
+    if columns(connection, "orders")?.iter().any(|column| column == "product_id") {
+        if let Err(e) = connection.execute_batch(SPLIT_ORDERS) {
+            let _ = connection.execute_batch("ROLLBACK");
+            return Err(e);
+        }
+    }
+    if !columns(connection, "order_lines")?.iter().any(|column| column == "unit_price") {
+        connection.execute_batch(ADD_PRICES)?;
+    }
+    Ok(())
+}
+
+fn columns(connection: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    connection.prepare("SELECT name FROM pragma_table_info(?1)")?
+        .query_map([table], |row| row.get(0))?
+        .collect()
+}
+
+fn kind_of(error: &rusqlite::Error) -> OrderErrorKind {
+    match error.sqlite_error_code() {
+        Some(ErrorCode::ConstraintViolation) => OrderErrorKind::Constraint,
+        _ => OrderErrorKind::Io,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    use crate::order::{LineItem, Quantity};
+
+    use super::*;
+
+    #[test]
+    fn should_load() {
+        let mut loader = SqliteLoader::to(Connection::open_in_memory().unwrap()).unwrap();
+
+        loader.load(order(12, "Nuts")).unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(loaded(&loader), vec![(12, "2019-08-27".to_string(), "123456789".to_string(), "Nuts".to_string(), "12.20".to_string(), "KG".to_string())]);
+    }
+
+    #[test]
+    fn should_load_line_items() {
+        let mut loader = SqliteLoader::to(Connection::open_in_memory().unwrap()).unwrap();
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_line_item(line_item("Nuts"))
+            .with_line_item(line_item("Jam"))
+            .build();
+
+        loader.load(order).unwrap();
+        loader.finish().unwrap();
+
+        let product_names: Vec<String> = loaded(&loader).into_iter().map(|line| line.3).collect();
+        assert_eq!(product_names, vec!["Nuts", "Jam"]);
+    }
+
+    #[test]
+    fn should_keep_flushed_orders_when_aborted() {
+        let mut loader = SqliteLoader::to(Connection::open_in_memory().unwrap()).unwrap();
+        loader.load(order(12, "Nuts")).unwrap();
+        loader.flush().unwrap();
+
+        loader.load(order(13, "Jam")).unwrap();
+        loader.abort();
+
+        let order_ids: Vec<u64> = loaded(&loader).into_iter().map(|line| line.0).collect();
+        assert_eq!(order_ids, vec![12]);
+    }
+
+    #[test]
+    fn should_discard_order_whose_id_exceeds_integers() {
+        let mut loader = SqliteLoader::to(Connection::open_in_memory().unwrap()).unwrap()
+            .with_conflict_policy(ConflictPolicy::Upsert);
+        loader.load(order(1, "Nuts")).unwrap();
+
+        let discarded_order = loader.load(order(u64::MAX, "Jam")).err().unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(discarded_order.kind(), OrderErrorKind::Encoding);
+        assert_eq!(discarded_order.error_message(), ID_OUT_OF_RANGE);
+        assert_eq!(loaded(&loader).into_iter().map(|line| (line.0, line.3)).collect::<Vec<_>>(), vec![(1, "Nuts".to_string())]);
+    }
+
+    #[test]
+    fn should_reject_existing_order() {
+        let mut loader = SqliteLoader::to(Connection::open_in_memory().unwrap()).unwrap();
+        loader.load(order(12, "Nuts")).unwrap();
+
+        let discarded_order = loader.load(order(12, "Jam")).err().unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(discarded_order.kind(), OrderErrorKind::Duplicate);
+        assert_eq!(discarded_order.error_message(), ORDER_EXISTS);
+        assert_eq!(loaded(&loader)[0].3, "Nuts");
+    }
+
+    #[test]
+    fn should_skip_existing_order() {
+        let mut loader = SqliteLoader::to(Connection::open_in_memory().unwrap()).unwrap()
+            .with_conflict_policy(ConflictPolicy::Skip);
+        loader.load(order(12, "Nuts")).unwrap();
+
+        loader.load(order(12, "Jam")).unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(loaded(&loader)[0].3, "Nuts");
+    }
+
+    #[test]
+    fn should_upsert_existing_order() {
+        let mut loader = SqliteLoader::to(Connection::open_in_memory().unwrap()).unwrap()
+            .with_conflict_policy(ConflictPolicy::Upsert);
+        loader.load(order(12, "Nuts")).unwrap();
+
+        loader.load(order(12, "Jam")).unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(loaded(&loader).len(), 1);
+        assert_eq!(loaded(&loader)[0].3, "Jam");
+    }
+
+    #[test]
+    fn should_roll_back_current_batch_when_aborted() {
+        let mut loader = SqliteLoader::to(Connection::open_in_memory().unwrap()).unwrap()
+            .with_batch_size(2);
+        loader.load(order(12, "Nuts")).unwrap();
+        loader.load(order(13, "Nuts")).unwrap();
+        loader.load(order(14, "Nuts")).unwrap();
+
+        loader.abort();
+
+        let ids: Vec<u64> = loaded(&loader).iter().map(|order| order.0).collect();
+        assert_eq!(ids, vec![12, 13]);
+    }
+
+    #[test]
+    fn should_roll_back_failing_order_alone() {
+        let mut loader = SqliteLoader::to(Connection::open_in_memory().unwrap()).unwrap();
+        loader.connection.execute_batch("CREATE TRIGGER poison BEFORE INSERT ON order_lines WHEN NEW.product_name = 'Poison' \
+                                         BEGIN SELECT RAISE(ABORT, 'poisoned'); END").unwrap();
+        let order_13 = Order::builder()
+            .with_id(13)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_line_item(line_item("Nuts"))
+            .with_line_item(line_item("Poison"))
+            .build();
+
+        loader.load(order(12, "Nuts")).unwrap();
+        let discarded_order = loader.load(order_13).err().unwrap();
+        loader.load(order(14, "Nuts")).unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(discarded_order.error_message(), "poisoned");
+        let ids: Vec<u64> = loaded(&loader).iter().map(|order| order.0).collect();
+        assert_eq!(ids, vec![12, 14]);
+        let order_count: i64 = loader.connection.query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0)).unwrap();
+        assert_eq!(order_count, 2);
+    }
+
+    #[test]
+    fn should_discard_whole_batch_failing_to_commit() {
+        let mut loader = SqliteLoader::to(Connection::open_in_memory().unwrap()).unwrap()
+            .with_batch_size(3);
+        loader.connection.execute_batch("PRAGMA foreign_keys = ON; \
+                                         CREATE TABLE audits (order_id INTEGER REFERENCES audited_orders (id) DEFERRABLE INITIALLY DEFERRED); \
+                                         CREATE TABLE audited_orders (id INTEGER PRIMARY KEY); \
+                                         CREATE TRIGGER audit AFTER INSERT ON orders WHEN NEW.id = 13 \
+                                         BEGIN INSERT INTO audits VALUES (NEW.id); END").unwrap();
+
+        loader.load(order(12, "Nuts")).unwrap();
+        loader.load(order(13, "Nuts")).unwrap();
+        let discarded_order = loader.load(order(14, "Nuts")).err().unwrap();
+        let rolled_back = loader.rolled_back();
+        loader.load(order(15, "Nuts")).unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(discarded_order.kind(), OrderErrorKind::Constraint);
+        assert_eq!(rolled_back.iter().map(|discarded_order| discarded_order.order().id()).collect::<Vec<_>>(), vec![12, 13]);
+        assert!(loader.rolled_back().is_empty());
+        let ids: Vec<u64> = loaded(&loader).iter().map(|order| order.0).collect();
+        assert_eq!(ids, vec![15]);
+    }
+
+    #[test]
+    fn should_load_price() {
+        let mut loader = SqliteLoader::to(Connection::open_in_memory().unwrap()).unwrap();
+        let order = Order::builder()
+            .with_id(12)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_line_item(line_item("Nuts"))
+            .with_line_item(LineItem::builder()
+                .with_product_id("987654321".to_string())
+                .with_product_name("Jam".to_string())
+                .with_quantity(Quantity::builder().with_quantity(Decimal::new(2, 0)).build())
+                .with_unit_price(Decimal::new(150, 2))
+                .with_currency("EUR".parse().unwrap())
+                .build())
+            .build();
+
+        loader.load(order).unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(prices(&loader), vec![(None, None, None), (Some("1.50".to_string()), Some("EUR".to_string()), Some("3.00".to_string()))]);
+    }
+
+    #[test]
+    fn should_migrate_orders_holding_their_product() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch("CREATE TABLE orders (id INTEGER PRIMARY KEY, date TEXT NOT NULL, product_id TEXT NOT NULL, \
+                                      product_name TEXT NOT NULL, quantity TEXT NOT NULL, unit TEXT NOT NULL);\
+                                  INSERT INTO orders VALUES (12, '2019-08-27', '123456789', 'Nuts', '12.20', 'KG')").unwrap();
+        let mut loader = SqliteLoader::to(connection).unwrap();
+
+        loader.load(order(13, "Jam")).unwrap();
+        loader.finish().unwrap();
+
+        let product_names: Vec<String> = loaded(&loader).into_iter().map(|line| line.3).collect();
+        assert_eq!(product_names, vec!["Nuts", "Jam"]);
+        assert_eq!(prices(&loader), vec![(None, None, None), (None, None, None)]);
+    }
+
+    #[test]
+    fn should_migrate_line_items_without_price() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch("CREATE TABLE orders (id INTEGER PRIMARY KEY, date TEXT NOT NULL);\
+                                  CREATE TABLE order_lines (order_id INTEGER NOT NULL REFERENCES orders (id), line INTEGER NOT NULL, \
+                                      product_id TEXT NOT NULL, product_name TEXT NOT NULL, quantity TEXT NOT NULL, unit TEXT NOT NULL, \
+                                      PRIMARY KEY (order_id, line))").unwrap();
+        let mut loader = SqliteLoader::to(connection).unwrap();
+
+        loader.load(order(12, "Nuts")).unwrap();
+        loader.finish().unwrap();
+
+        assert_eq!(prices(&loader), vec![(None, None, None)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn loader_requires_batch_size_greater_than_0() {
+        SqliteLoader::to(Connection::open_in_memory().unwrap()).unwrap()
+            .with_batch_size(0);
+    }
+
+    fn order(id: u64, product_name: &str) -> Order {
+        Order::builder()
+            .with_id(id)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+            .with_line_item(line_item(product_name))
+            .build()
+    }
+
+    fn line_item(product_name: &str) -> LineItem {
+        LineItem::builder()
+            .with_product_id("123456789".to_string())
+            .with_product_name(product_name.to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(1220, 2)).build())
+            .build()
+    }
+
+    fn loaded(loader: &SqliteLoader) -> Vec<(u64, String, String, String, String, String)> {
+        let mut statement = loader.connection
+            .prepare("SELECT id, date, product_id, product_name, quantity, unit \
+                      FROM orders JOIN order_lines ON order_id = id ORDER BY id, line")
+            .unwrap();
+        statement.query_map([], |row| Ok((row.get::<_, i64>(0)? as u64, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    fn prices(loader: &SqliteLoader) -> Vec<(Option<String>, Option<String>, Option<String>)> {
+        let mut statement = loader.connection
+            .prepare("SELECT unit_price, currency, total FROM order_lines ORDER BY order_id, line")
+            .unwrap();
+        statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+}
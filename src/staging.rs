@@ -0,0 +1,72 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Output file written under a temporary name next to its final path, and renamed only once complete, so that an
+/// aborted run leaves neither a partial output nor the previous one truncated.
+#[derive(Debug)]
+pub struct Staging {
+    temporary_path: PathBuf,
+    path: PathBuf,
+}
+
+impl Staging {
+    /// Creates the temporary file of the given path (the path suffixed with `.part`), to be written in its place.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<(File, Staging)> {
+        let path = path.as_ref().to_path_buf();
+        let mut temporary_path = OsString::from(&path);
+        temporary_path.push(".part");
+        let temporary_path = PathBuf::from(temporary_path);
+        let file = File::create(&temporary_path)?;
+        Ok((file, Staging { temporary_path, path }))
+    }
+
+    /// Moves the temporary file to the final path, replacing any file there.
+    pub fn commit(self) -> io::Result<()> {
+        fs::rename(&self.temporary_path, &self.path)
+    }
+
+    /// Removes the temporary file, leaving the final path untouched.
+    pub fn discard(self) {
+        let _ = fs::remove_file(&self.temporary_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn should_replace_output_once_committed() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("orders.csv");
+        fs::write(&path, "previous").unwrap();
+
+        let (mut file, staging) = Staging::create(&path).unwrap();
+        file.write_all(b"current").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "previous");
+        staging.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "current");
+        assert!(!directory.path().join("orders.csv.part").exists());
+    }
+
+    #[test]
+    fn should_keep_previous_output_when_discarded() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("orders.csv");
+        fs::write(&path, "previous").unwrap();
+
+        let (mut file, staging) = Staging::create(&path).unwrap();
+        file.write_all(b"partial").unwrap();
+        staging.discard();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "previous");
+        assert!(!directory.path().join("orders.csv.part").exists());
+    }
+}
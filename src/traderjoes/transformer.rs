@@ -1,12 +1,11 @@
-use std::str::FromStr;
-
 use chrono::NaiveDate;
 use rust_decimal::Decimal;
 use rust_decimal::prelude::Zero;
 
-use crate::order::{Order, Quantity};
+use crate::order::{Order, Quantity, Unit};
 use crate::record::Record;
-use crate::transformer::{DiscardedRecord, Transformer};
+use crate::transformer::{DiscardedRecord, FieldError, RecordErrorKind, Transformer};
+use crate::value::Value;
 
 const ORDER_NUMBER: &str = "Order Number";
 const YEAR: &str = "Year";
@@ -30,46 +29,46 @@ impl TraderJoesTransformer {
     }
 }
 
+impl Default for TraderJoesTransformer {
+    fn default() -> Self {
+        TraderJoesTransformer::new()
+    }
+}
+
 impl<R: Record> Transformer<R> for TraderJoesTransformer {
+    /// Checks every field, so that a discarded record comes with all its errors.
     fn transform(&self, mut record: R) -> Result<Order, DiscardedRecord> {
-        let order_number = record.value_for(ORDER_NUMBER)
-            .and_then(|value| value.parse::<u64>().ok())
-            .filter(|value| value > &0);
-        if order_number.is_none() {
-            return Err(DiscardedRecord::new(record.id(), INVALID_ORDER_NUMBER.to_string()));
-        }
+        let mut errors = Vec::new();
 
-        let date = parse_date(&mut record);
-        if date.is_none() {
-            return Err(DiscardedRecord::new(record.id(), INVALID_DATE.to_string()));
-        }
+        let order_number = check(record.typed_value_for(ORDER_NUMBER).as_deref(), |value| value.to_uint().ok(), |value| value > &0);
+        let order_number = checked("id", order_number, INVALID_ORDER_NUMBER, &mut errors);
 
-        let product_number = record.value_for(PRODUCT_NUMBER)
-            .filter(|value| value.chars().all(|x| x.is_alphanumeric()));
-        if product_number.is_none() {
-            return Err(DiscardedRecord::new(record.id(), INVALID_PRODUCT_NUMBER.to_string()));
-        }
+        let date = parse_date(&mut record).ok_or_else(|| date_error(&mut record));
+        let date = checked("date", date, INVALID_DATE, &mut errors);
 
-        let product_name = record.value_for(PRODUCT_NAME)
-            .filter(|value| value.chars().all(|x| x.is_alphabetic()));
-        if product_name.is_none() {
-            return Err(DiscardedRecord::new(record.id(), INVALID_PRODUCT_NAME.to_string()));
-        }
+        let product_number = check(record.typed_value_for(PRODUCT_NUMBER).as_deref(), |value| Some(value.to_string()),
+                                   |value| value.chars().all(|x| x.is_alphanumeric()));
+        let product_number = checked("product_id", product_number, INVALID_PRODUCT_NUMBER, &mut errors);
 
-        let count = record.value_for(COUNT)
-            .and_then(|value| Decimal::from_str(&value).ok())
-            .filter(|value| value > &Decimal::zero());
-        if count.is_none() {
-            return Err(DiscardedRecord::new(record.id(), INVALID_COUNT.to_string()));
+        let product_name = check(record.typed_value_for(PRODUCT_NAME).as_deref(), |value| value.as_str().map(str::to_owned),
+                                 |value| value.chars().all(|x| x.is_alphabetic()));
+        let product_name = checked("product_name", product_name, INVALID_PRODUCT_NAME, &mut errors);
+
+        let count = check(record.typed_value_for(COUNT).as_deref(), |value| value.to_decimal().ok(), |value| value > &Decimal::zero());
+        let count = checked("quantity", count, INVALID_COUNT, &mut errors);
+
+        if !errors.is_empty() {
+            return Err(DiscardedRecord::with_errors(&record, errors));
         }
 
         let order = Order::builder()
             .with_id(order_number.unwrap())
             .with_date(date.unwrap())
-            .with_product_id(product_number.unwrap().to_owned())
-            .with_product_name(product_name.unwrap().to_owned())
+            .with_product_id(product_number.unwrap())
+            .with_product_name(product_name.unwrap())
             .with_quantity(Quantity::builder()
                 .with_quantity(count.unwrap())
+                .with_unit(Unit::EACH)
                 .build())
             .build();
 
@@ -77,6 +76,26 @@ impl<R: Record> Transformer<R> for TraderJoesTransformer {
     }
 }
 
+/// Coerces and validates a value, telling why it is rejected; a null value is missing.
+fn check<T, P, V>(value: Option<&Value>, parse: P, valid: V) -> Result<T, RecordErrorKind>
+    where P: Fn(&Value) -> Option<T>, V: Fn(&T) -> bool {
+    let value = value.filter(|value| !value.is_null()).ok_or(RecordErrorKind::Missing)?;
+    let value = parse(value).ok_or(RecordErrorKind::Malformed)?;
+    if valid(&value) { Ok(value) } else { Err(RecordErrorKind::Invalid) }
+}
+
+fn checked<T>(field: &str, value: Result<T, RecordErrorKind>, message: &str, errors: &mut Vec<FieldError>) -> Option<T> {
+    value.map_err(|kind| errors.push(FieldError::new(field, kind, message.to_string()))).ok()
+}
+
+fn date_error<R: Record>(record: &mut R) -> RecordErrorKind {
+    if [YEAR, MONTH, DAY].iter().all(|column| record.value_for(column).is_some()) {
+        RecordErrorKind::Malformed
+    } else {
+        RecordErrorKind::Missing
+    }
+}
+
 pub fn parse_date<R: Record>(record: &mut R) -> Option<NaiveDate> {
     let year = record.value_for(YEAR)?;
     let month = record.value_for(MONTH)?;
@@ -100,7 +119,42 @@ mod tests {
 
         let result = transformer.transform(record);
 
-        assert_eq!(result.err().unwrap().error_message(), INVALID_ORDER_NUMBER);
+        assert_eq!(result.err().unwrap().errors()[0], FieldError::new("id", RecordErrorKind::Invalid, INVALID_ORDER_NUMBER.to_string()));
+    }
+
+    #[test]
+    fn order_number_may_exceed_range_of_ints() {
+        let map = vec![
+            (ORDER_NUMBER.to_string(), "9223372036854775808".to_string()),
+        ].into_iter().collect();
+        let record = MapRecord::new(1, map);
+        let transformer = TraderJoesTransformer::new();
+
+        let result = transformer.transform(record);
+
+        assert!(!result.err().unwrap().errors().iter().any(|error| error.field() == Some("id")));
+    }
+
+    #[test]
+    fn should_report_every_invalid_field() {
+        let map = vec![
+            (ORDER_NUMBER.to_string(), "1".to_string()),
+            (YEAR.to_string(), "2019".to_string()),
+            (MONTH.to_string(), "13".to_string()),
+            (DAY.to_string(), "24".to_string()),
+            (PRODUCT_NUMBER.to_string(), "12345".to_string()),
+            (PRODUCT_NAME.to_string(), "Jam".to_string()),
+            (COUNT.to_string(), "many".to_string()),
+        ].into_iter().collect();
+        let record = MapRecord::new(1, map);
+        let transformer = TraderJoesTransformer::new();
+
+        let result = transformer.transform(record);
+
+        assert_eq!(result.err().unwrap().errors(), &[
+            FieldError::new("date", RecordErrorKind::Malformed, INVALID_DATE.to_string()),
+            FieldError::new("quantity", RecordErrorKind::Malformed, INVALID_COUNT.to_string()),
+        ]);
     }
 
     #[test]
@@ -140,10 +194,54 @@ mod tests {
 
         assert_eq!(result.ok().unwrap(), Order::builder()
             .with_id(1)
-            .with_date(NaiveDate::from_ymd(2019, 8, 24))
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 24).unwrap())
+            .with_product_id("12345".to_string())
+            .with_product_name("Jam".to_string())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(10020, 2)).with_unit(Unit::EACH).build())
+            .build());
+    }
+
+    #[test]
+    fn should_transform_typed_values() {
+        let record = MapRecord::with_typed_columns(1, vec![
+            (ORDER_NUMBER.to_string(), Value::Decimal(Decimal::from(u64::MAX))),
+            (YEAR.to_string(), Value::Int(2019)),
+            (MONTH.to_string(), Value::Int(8)),
+            (DAY.to_string(), Value::Int(24)),
+            (PRODUCT_NUMBER.to_string(), Value::Int(12345)),
+            (PRODUCT_NAME.to_string(), Value::from("Jam")),
+            (COUNT.to_string(), Value::Decimal(Decimal::new(10020, 2))),
+        ]);
+        let transformer = TraderJoesTransformer::new();
+
+        let result = transformer.transform(record);
+
+        assert_eq!(result.ok().unwrap(), Order::builder()
+            .with_id(u64::MAX)
+            .with_date(NaiveDate::from_ymd_opt(2019, 8, 24).unwrap())
             .with_product_id("12345".to_string())
             .with_product_name("Jam".to_string())
-            .with_quantity(Quantity::builder().with_quantity(Decimal::new(10020, 2)).build())
+            .with_quantity(Quantity::builder().with_quantity(Decimal::new(10020, 2)).with_unit(Unit::EACH).build())
             .build());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_tell_missing_typed_values() {
+        let record = MapRecord::with_typed_columns(1, vec![
+            (ORDER_NUMBER.to_string(), Value::Int(1)),
+            (YEAR.to_string(), Value::Int(2019)),
+            (MONTH.to_string(), Value::Int(8)),
+            (DAY.to_string(), Value::Int(24)),
+            (PRODUCT_NUMBER.to_string(), Value::Int(12345)),
+            (PRODUCT_NAME.to_string(), Value::Null),
+            (COUNT.to_string(), Value::Decimal(Decimal::new(10020, 2))),
+        ]);
+        let transformer = TraderJoesTransformer::new();
+
+        let result = transformer.transform(record);
+
+        assert_eq!(result.err().unwrap().errors(), &[
+            FieldError::new("product_name", RecordErrorKind::Missing, INVALID_PRODUCT_NAME.to_string()),
+        ]);
+    }
+}
@@ -1,26 +1,209 @@
-use crate::order::Order;
-use crate::record::Record;
+use std::fmt;
+
+use crate::order::{Order, Unit};
+use crate::record::{Provenance, Record};
+
+const NO_ERROR: &str = "Record discarded without an error.";
 
 pub trait Transformer<R: Record> {
     fn transform(&self, record: R) -> Result<Order, DiscardedRecord>;
 }
 
+impl<R: Record, T: Transformer<R> + ?Sized> Transformer<R> for Box<T> {
+    fn transform(&self, record: R) -> Result<Order, DiscardedRecord> {
+        (**self).transform(record)
+    }
+}
+
+/// Converts the quantities of the orders of another transformer to a target unit, discarding the records whose
+/// quantity cannot be converted. The other transformer borrows each record, which is kept to be discarded if need be.
+pub struct NormalisingTransformer<T> {
+    transformer: T,
+    unit: Unit,
+}
+
+impl<T> NormalisingTransformer<T> {
+    pub fn to(unit: Unit, transformer: T) -> Self {
+        NormalisingTransformer { transformer, unit }
+    }
+}
+
+impl<R: Record, T: for<'a> Transformer<&'a R>> Transformer<R> for NormalisingTransformer<T> {
+    fn transform(&self, record: R) -> Result<Order, DiscardedRecord> {
+        self.transformer.transform(&record)?
+            .normalise_to(self.unit)
+            .map_err(|e| DiscardedRecord::with_errors(&record, vec![FieldError::new("quantity", RecordErrorKind::Conversion, e.to_string())]))
+    }
+}
+
+/// Record the transformer could not turn into an order, with its raw values and every error found in it.
 #[derive(Debug)]
 pub struct DiscardedRecord {
     id: u64,
-    error_message: String,
+    values: Vec<(String, String)>,
+    provenance: Box<Provenance>,
+    errors: Vec<FieldError>,
 }
 
 impl DiscardedRecord {
-    pub fn new(id: u64, error_message: String) -> DiscardedRecord {
-        DiscardedRecord { id, error_message }
+    /// Discards a record for a single error not tied to a field.
+    pub fn new<R: Record + ?Sized>(record: &R, kind: RecordErrorKind, error_message: String) -> DiscardedRecord {
+        DiscardedRecord::with_errors(record, vec![FieldError { field: None, kind, message: error_message }])
+    }
+
+    /// Discards a record for the given errors; a record discarded without any gets a generic one, so that every
+    /// discarded record tells at least one error.
+    pub fn with_errors<R: Record + ?Sized>(record: &R, mut errors: Vec<FieldError>) -> DiscardedRecord {
+        if errors.is_empty() {
+            errors.push(FieldError { field: None, kind: RecordErrorKind::Invalid, message: NO_ERROR.to_string() });
+        }
+        DiscardedRecord { id: record.id(), values: owned_values(record), provenance: Box::new(record.provenance().clone()), errors }
     }
 
     pub fn id(&self) -> u64 {
         self.id
     }
 
-    pub fn error_message(&self) -> &str {
-        &self.error_message
+    /// Raw values of the record by column name, as extracted.
+    pub fn values(&self) -> &[(String, String)] {
+        &self.values
+    }
+
+    /// Where the record was extracted from.
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    /// Messages of all the errors, separated by `; `.
+    pub fn error_message(&self) -> String {
+        self.errors.iter().map(FieldError::message).collect::<Vec<_>>().join("; ")
+    }
+}
+
+fn owned_values<R: Record + ?Sized>(record: &R) -> Vec<(String, String)> {
+    record.values().into_iter()
+        .map(|(column, value)| (column.to_owned(), value.to_owned()))
+        .collect()
+}
+
+/// Error found in a record: which field failed, if any, why, and a message for humans.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FieldError {
+    field: Option<String>,
+    kind: RecordErrorKind,
+    message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, kind: RecordErrorKind, message: String) -> FieldError {
+        FieldError { field: Some(field.to_string()), kind, message }
+    }
+
+    pub fn field(&self) -> Option<&str> {
+        self.field.as_deref()
+    }
+
+    pub fn kind(&self) -> RecordErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Machine-readable reason a record was discarded.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RecordErrorKind {
+    /// The value is missing from the record.
+    Missing,
+    /// The value cannot be parsed, e.g. a number or a date with a wrong format.
+    Malformed,
+    /// The value parses but breaks a validation rule, e.g. a non-positive count.
+    Invalid,
+    /// The value cannot be converted, e.g. a quantity to a unit of another dimension.
+    Conversion,
+}
+
+impl RecordErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RecordErrorKind::Missing => "missing",
+            RecordErrorKind::Malformed => "malformed",
+            RecordErrorKind::Invalid => "invalid",
+            RecordErrorKind::Conversion => "conversion",
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(formatter, "{}: {}", field, self.message),
+            None => write!(formatter, "{}", self.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    use crate::order::Quantity;
+    use crate::record::MapRecord;
+
+    use super::*;
+
+    #[test]
+    fn should_normalise_quantity() {
+        let transformer = NormalisingTransformer::to(Unit::KG, FixedTransformer { unit: Unit::G });
+
+        let order = transformer.transform(MapRecord::new(1, vec![].into_iter().collect())).unwrap();
+
+        assert_eq!(order.line_items()[0].quantity().quantity(), &Decimal::new(125, 2));
+        assert_eq!(order.line_items()[0].quantity().unit(), &Unit::KG);
+    }
+
+    #[test]
+    fn should_discard_record_with_incompatible_unit() {
+        let transformer = NormalisingTransformer::to(Unit::KG, FixedTransformer { unit: Unit::ML });
+
+        let discarded_record = transformer.transform(MapRecord::new(7, vec![
+            ("Count".to_string(), "12".to_string()),
+        ].into_iter().collect())).err().unwrap();
+
+        assert_eq!(discarded_record.id(), 7);
+        assert_eq!(discarded_record.values(), &[("Count".to_string(), "12".to_string())]);
+        assert_eq!(discarded_record.errors(), &[FieldError::new("quantity", RecordErrorKind::Conversion, "cannot convert ML to KG".to_string())]);
+    }
+
+    #[test]
+    fn should_discard_record_without_errors_for_generic_error() {
+        let discarded_record = DiscardedRecord::with_errors(&MapRecord::new(7, vec![].into_iter().collect()), vec![]);
+
+        assert_eq!(discarded_record.errors().len(), 1);
+        assert_eq!(discarded_record.errors()[0].kind(), RecordErrorKind::Invalid);
+        assert_eq!(discarded_record.error_message(), NO_ERROR);
+    }
+
+    struct FixedTransformer {
+        unit: Unit,
+    }
+
+    impl<R: Record> Transformer<R> for FixedTransformer {
+        fn transform(&self, _record: R) -> Result<Order, DiscardedRecord> {
+            Ok(Order::builder()
+                .with_id(1)
+                .with_date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap())
+                .with_product_id("123456789".to_string())
+                .with_product_name("Nuts".to_string())
+                .with_quantity(Quantity::builder().with_quantity(Decimal::new(1250, 0)).with_unit(self.unit).build())
+                .build())
+        }
     }
 }
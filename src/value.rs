@@ -0,0 +1,221 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const DATE_TIME_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"];
+
+/// Typed value of a record field, as told by its source: sources without types, such as CSV, only have strings.
+/// Values are coerced to the type a transformer expects on demand, parsing strings when needed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Decimal(Decimal),
+    String(String),
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+    Bytes(Vec<u8>),
+}
+
+/// Failure to coerce a value to another type.
+#[derive(Debug, Eq, PartialEq)]
+pub struct CoercionError {
+    value: String,
+    target: &'static str,
+}
+
+impl fmt::Display for CoercionError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "cannot coerce `{}` to {}", self.value, self.target)
+    }
+}
+
+impl std::error::Error for CoercionError {}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+
+    /// The string of a string value, without coercion.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Accepts integers 0 and 1, and strings `true`, `false`, `yes`, `no`, `1` and `0` whatever their case.
+    pub fn to_bool(&self) -> Result<bool, CoercionError> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            Value::Int(0) => Ok(false),
+            Value::Int(1) => Ok(true),
+            Value::String(value) => match value.trim().to_lowercase().as_str() {
+                "true" | "yes" | "1" => Ok(true),
+                "false" | "no" | "0" => Ok(false),
+                _ => Err(self.coercion_error("bool")),
+            },
+            _ => Err(self.coercion_error("bool")),
+        }
+    }
+
+    /// Accepts decimals without a fractional part, and strings of integers.
+    pub fn to_int(&self) -> Result<i64, CoercionError> {
+        match self {
+            Value::Int(value) => Ok(*value),
+            Value::Decimal(value) if value.fract().is_zero() => value.to_i64().ok_or_else(|| self.coercion_error("int")),
+            Value::String(value) => value.trim().parse().map_err(|_| self.coercion_error("int")),
+            _ => Err(self.coercion_error("int")),
+        }
+    }
+
+    /// Accepts integers which are not negative, and decimals and strings of such integers, up to `u64::MAX`; suits
+    /// ids, which may exceed the range of integers.
+    pub fn to_uint(&self) -> Result<u64, CoercionError> {
+        match self {
+            Value::Int(value) => value.to_u64().ok_or_else(|| self.coercion_error("unsigned int")),
+            Value::Decimal(value) if value.fract().is_zero() => value.to_u64().ok_or_else(|| self.coercion_error("unsigned int")),
+            Value::String(value) => value.trim().parse().map_err(|_| self.coercion_error("unsigned int")),
+            _ => Err(self.coercion_error("unsigned int")),
+        }
+    }
+
+    /// Accepts integers, and strings of numbers, in scientific notation included.
+    pub fn to_decimal(&self) -> Result<Decimal, CoercionError> {
+        match self {
+            Value::Int(value) => Ok(Decimal::from(*value)),
+            Value::Decimal(value) => Ok(*value),
+            Value::String(value) => {
+                let value = value.trim();
+                Decimal::from_str(value)
+                    .or_else(|_| Decimal::from_scientific(value))
+                    .map_err(|_| self.coercion_error("decimal"))
+            }
+            _ => Err(self.coercion_error("decimal")),
+        }
+    }
+
+    /// Accepts date times, whose time is dropped, and strings of ISO 8601 dates (`2019-08-27`).
+    pub fn to_date(&self) -> Result<NaiveDate, CoercionError> {
+        match self {
+            Value::Date(value) => Ok(*value),
+            Value::DateTime(value) => Ok(value.date()),
+            Value::String(value) => NaiveDate::parse_from_str(value.trim(), DATE_FORMAT).map_err(|_| self.coercion_error("date")),
+            _ => Err(self.coercion_error("date")),
+        }
+    }
+
+    /// Accepts dates, at midnight, and strings of ISO 8601 date times with a `T` or a space between date and time
+    /// (`2019-08-27T10:30:00`), or of dates.
+    pub fn to_date_time(&self) -> Result<NaiveDateTime, CoercionError> {
+        match self {
+            Value::Date(value) => Ok(value.and_time(NaiveTime::MIN)),
+            Value::DateTime(value) => Ok(*value),
+            Value::String(value) => {
+                let value = value.trim();
+                DATE_TIME_FORMATS.iter()
+                    .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+                    .or_else(|| NaiveDate::parse_from_str(value, DATE_FORMAT).ok().map(|date| date.and_time(NaiveTime::MIN)))
+                    .ok_or_else(|| self.coercion_error("date time"))
+            }
+            _ => Err(self.coercion_error("date time")),
+        }
+    }
+
+    /// Accepts strings, as their UTF-8 bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CoercionError> {
+        match self {
+            Value::Bytes(value) => Ok(value.clone()),
+            Value::String(value) => Ok(value.as_bytes().to_vec()),
+            _ => Err(self.coercion_error("bytes")),
+        }
+    }
+
+    fn coercion_error(&self, target: &'static str) -> CoercionError {
+        CoercionError { value: self.to_string(), target }
+    }
+}
+
+/// Text of a value, as found in a CSV file: nulls are empty, date times use a `T` separator and bytes are written in
+/// hexadecimal.
+impl fmt::Display for Value {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Bool(value) => write!(formatter, "{}", value),
+            Value::Int(value) => write!(formatter, "{}", value),
+            Value::Decimal(value) => write!(formatter, "{}", value),
+            Value::String(value) => write!(formatter, "{}", value),
+            Value::Date(value) => write!(formatter, "{}", value.format(DATE_FORMAT)),
+            Value::DateTime(value) => write!(formatter, "{}", value.format(DATE_TIME_FORMATS[0])),
+            Value::Bytes(value) => value.iter().try_for_each(|byte| write!(formatter, "{:02x}", byte)),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_coerce_strings() {
+        assert_eq!(Value::from(" Yes").to_bool(), Ok(true));
+        assert_eq!(Value::from("12").to_int(), Ok(12));
+        assert_eq!(Value::from("18446744073709551615").to_uint(), Ok(u64::MAX));
+        assert_eq!(Value::from("12.20").to_decimal(), Ok(Decimal::new(1220, 2)));
+        assert_eq!(Value::from("1.22e1").to_decimal(), Ok(Decimal::new(122, 1)));
+        assert_eq!(Value::from("2019-08-27").to_date(), Ok(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap()));
+        assert_eq!(Value::from("2019-08-27 10:30:00").to_date_time(), Ok(date_time(2019, 8, 27, 10, 30)));
+        assert_eq!(Value::from("2019-08-27").to_date_time(), Ok(date_time(2019, 8, 27, 0, 0)));
+        assert_eq!(Value::from("Nuts").to_bytes(), Ok(b"Nuts".to_vec()));
+    }
+
+    #[test]
+    fn should_coerce_between_types() {
+        assert_eq!(Value::Int(1).to_bool(), Ok(true));
+        assert_eq!(Value::Int(12).to_decimal(), Ok(Decimal::new(12, 0)));
+        assert_eq!(Value::Decimal(Decimal::new(1200, 2)).to_int(), Ok(12));
+        assert_eq!(Value::Decimal(Decimal::from(u64::MAX)).to_uint(), Ok(u64::MAX));
+        assert_eq!(Value::DateTime(date_time(2019, 8, 27, 10, 30)).to_date(), Ok(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap()));
+    }
+
+    #[test]
+    fn should_tell_failed_coercions() {
+        assert_eq!(Value::from("many").to_decimal().unwrap_err().to_string(), "cannot coerce `many` to decimal");
+        assert_eq!(Value::Decimal(Decimal::new(1220, 2)).to_int().unwrap_err().to_string(), "cannot coerce `12.20` to int");
+        assert_eq!(Value::Int(-1).to_uint().unwrap_err().to_string(), "cannot coerce `-1` to unsigned int");
+        assert!(Value::Null.to_bool().is_err());
+    }
+
+    #[test]
+    fn should_display_values_as_text() {
+        assert_eq!(Value::Null.to_string(), "");
+        assert_eq!(Value::Bool(true).to_string(), "true");
+        assert_eq!(Value::Decimal(Decimal::new(1220, 2)).to_string(), "12.20");
+        assert_eq!(Value::Date(NaiveDate::from_ymd_opt(2019, 8, 27).unwrap()).to_string(), "2019-08-27");
+        assert_eq!(Value::DateTime(date_time(2019, 8, 27, 10, 30)).to_string(), "2019-08-27T10:30:00");
+        assert_eq!(Value::Bytes(vec![0xca, 0xfe]).to_string(), "cafe");
+    }
+
+    fn date_time(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+}
@@ -0,0 +1,308 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use flate2::write::GzEncoder;
+use tempfile::tempdir;
+
+#[test]
+fn should_run_pipeline() {
+    let directory = tempdir().unwrap();
+    let source = write_source(directory.path(), "13,2019,8,27,123456789,Nuts,12\n\
+                                                 16,2019,8,28,987654321,Jam,1\n");
+    let output = directory.path().join("orders.csv");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--transformer", "traderjoes", "--output", output.to_str().unwrap(), "--fail-on-discard"])
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(output).unwrap(), "Order Id,Date Time,Product Id,Product Name,Quantity,Unit,Unit Price,Currency,Total\n\
+                    13,2019-08-27,123456789,Nuts,12,EACH,,,\n\
+                    16,2019-08-28,987654321,Jam,1,EACH,,,\n");
+    assert!(String::from_utf8(result.stderr).unwrap().contains("loaded: 2 (discarded: 0)"));
+}
+
+#[test]
+fn should_fail_on_discard_when_asked() {
+    let directory = tempdir().unwrap();
+    let source = write_source(directory.path(), "13,2019,8,27,123456789,Nuts,12\n\
+                                                 16,2019,8,32,987654321,Jam,0\n");
+    let output = directory.path().join("orders.ndjson");
+    let rejects = directory.path().join("rejects.txt");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--transformer", "mappings/traderjoes.toml", "--output", output.to_str().unwrap(),
+            "--rejects", rejects.to_str().unwrap(), "--fail-on-discard"])
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(3));
+    assert_eq!(fs::read_to_string(output).unwrap(), "{\"id\":13,\"date\":\"2019-08-27\",\"line_items\":[{\"product_id\":\"123456789\",\"product_name\":\"Nuts\",\"quantity\":12,\"unit\":\"EACH\"}]}\n");
+    assert_eq!(fs::read_to_string(rejects).unwrap(), "record 2: date: Invalid date.\nrecord 2: quantity: Invalid count.\n");
+    assert!(String::from_utf8(result.stderr).unwrap().contains("discarded records:\n  Invalid count.: 1\n  Invalid date.: 1"));
+}
+
+#[test]
+fn should_report_malformed_source_rows() {
+    let directory = tempdir().unwrap();
+    let source = write_source(directory.path(), "13,2019,8,27,123456789,Nuts,12\n\
+                                                 16,2019,8,28\n");
+    let output = directory.path().join("orders.ndjson");
+    let rejects = directory.path().join("rejects.txt");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--transformer", "traderjoes", "--output", output.to_str().unwrap(),
+            "--rejects", rejects.to_str().unwrap()])
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(rejects).unwrap(), "record 2: CSV error: record 2 (line: 3, byte: 93): found record with 4 fields, \
+                                                       but the previous record has 7 fields\n");
+    assert!(String::from_utf8(result.stderr).unwrap().contains("failed extractions:\n  wrong number of fields: 1"));
+}
+
+#[test]
+fn should_fail_on_malformed_source_row_when_asked() {
+    let directory = tempdir().unwrap();
+    let source = write_source(directory.path(), "13,2019,8,27,123456789,Nuts,12\n\
+                                                 16,2019,8,28\n");
+    let output = directory.path().join("orders.ndjson");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--transformer", "traderjoes", "--output", output.to_str().unwrap(), "--fail-on-discard"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("failed extractions:\n  wrong number of fields: 1"));
+}
+
+#[test]
+fn should_transcode_source() {
+    let directory = tempdir().unwrap();
+    let source = directory.path().join("source.csv");
+    fs::write(&source, b"Order Number,Year,Month,Day,Product Number,Product Name,Count\n\
+                         13,2019,8,27,123456789,Cr\xe8me,12\n").unwrap();
+    let output = directory.path().join("orders.csv");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--encoding", "windows-1252", "--transformer", "traderjoes",
+            "--output", output.to_str().unwrap(), "--fail-on-discard"])
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(output).unwrap(), "Order Id,Date Time,Product Id,Product Name,Quantity,Unit,Unit Price,Currency,Total\n\
+                    13,2019-08-27,123456789,Crème,12,EACH,,,\n");
+    assert!(String::from_utf8(result.stderr).unwrap().contains("loaded: 1 (discarded: 0)"));
+}
+
+#[test]
+fn should_read_and_write_compressed_files() {
+    let directory = tempdir().unwrap();
+    let source = directory.path().join("source.csv.gz");
+    let mut encoder = GzEncoder::new(fs::File::create(&source).unwrap(), flate2::Compression::default());
+    encoder.write_all(b"Order Number,Year,Month,Day,Product Number,Product Name,Count\n\
+                        13,2019,8,27,123456789,Nuts,12\n").unwrap();
+    encoder.finish().unwrap();
+    let output = directory.path().join("orders.ndjson.zst");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--transformer", "traderjoes", "--output", output.to_str().unwrap(), "--fail-on-discard"])
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(String::from_utf8(zstd::decode_all(fs::File::open(output).unwrap()).unwrap()).unwrap(),
+               "{\"id\":13,\"date\":\"2019-08-27\",\"line_items\":[{\"product_id\":\"123456789\",\"product_name\":\"Nuts\",\"quantity\":12,\"unit\":\"EACH\"}]}\n");
+    assert!(String::from_utf8(result.stderr).unwrap().contains("loaded: 1 (discarded: 0)"));
+}
+
+#[test]
+fn should_extract_files_matching_glob() {
+    let directory = tempdir().unwrap();
+    fs::create_dir(directory.path().join("stores")).unwrap();
+    fs::write(directory.path().join("stores").join("east.csv"), "Order Number,Year,Month,Day,Product Number,Product Name,Count\n\
+                                                                  13,2019,8,27,123456789,Nuts,12\n").unwrap();
+    fs::write(directory.path().join("stores").join("west.csv"), "Product Name,Product Number,Order Number,Year,Month,Day,Count\n\
+                                                                  Jam,223456789,16,2019,8,28,two\n").unwrap();
+    let source = directory.path().join("stores").join("*.csv");
+    let output = directory.path().join("orders.csv");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--transformer", "traderjoes", "--output", output.to_str().unwrap()])
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(0));
+    let west = directory.path().join("stores").join("west.csv");
+    assert!(String::from_utf8(result.stderr).unwrap().starts_with(&format!("record 2 ({}:2): quantity: Invalid count.\n", west.display())));
+    assert_eq!(fs::read_to_string(output).unwrap(), "Order Id,Date Time,Product Id,Product Name,Quantity,Unit,Unit Price,Currency,Total\n\
+                    13,2019-08-27,123456789,Nuts,12,EACH,,,\n");
+}
+
+#[test]
+fn should_extract_existing_file_named_like_glob() {
+    let directory = tempdir().unwrap();
+    let source = directory.path().join("store[1].csv");
+    fs::write(&source, "Order Number,Year,Month,Day,Product Number,Product Name,Count\n\
+                        13,2019,8,27,123456789,Nuts,12\n").unwrap();
+    let output = directory.path().join("orders.csv");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--transformer", "traderjoes", "--output", output.to_str().unwrap()])
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(output).unwrap(), "Order Id,Date Time,Product Id,Product Name,Quantity,Unit,Unit Price,Currency,Total\n\
+                    13,2019-08-27,123456789,Nuts,12,EACH,,,\n");
+    assert!(String::from_utf8(result.stderr).unwrap().contains("loaded: 1 (discarded: 0)"));
+}
+
+#[test]
+fn should_refuse_checkpoint_of_glob_source() {
+    let directory = tempdir().unwrap();
+    write_source(directory.path(), "13,2019,8,27,123456789,Nuts,12\n");
+    let source = directory.path().join("*.csv");
+    let output = directory.path().join("orders.csv");
+    let checkpoint = directory.path().join("checkpoint");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--transformer", "traderjoes", "--output", output.to_str().unwrap(),
+            "--checkpoint", checkpoint.to_str().unwrap()])
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(1));
+    assert!(String::from_utf8(result.stderr).unwrap().contains("cannot be resumed with --checkpoint"));
+    assert!(!output.exists());
+}
+
+#[test]
+fn should_refuse_checkpoint_of_output_which_cannot_be_resumed() {
+    let directory = tempdir().unwrap();
+    let source = write_source(directory.path(), "13,2019,8,27,123456789,Nuts,12\n");
+    let checkpoint = directory.path().join("checkpoint");
+
+    for (output, error) in [("orders.csv.gz", "a compressed output cannot be resumed"), ("orders.json", "a JSON array output cannot be resumed")] {
+        let output = directory.path().join(output);
+        let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+            .args(["--source", source.to_str().unwrap(), "--transformer", "traderjoes", "--output", output.to_str().unwrap(),
+                "--checkpoint", checkpoint.to_str().unwrap()])
+            .output()
+            .unwrap();
+
+        assert_eq!(result.status.code(), Some(1));
+        assert!(String::from_utf8(result.stderr).unwrap().contains(error));
+        assert!(!output.exists());
+        assert!(!checkpoint.exists());
+    }
+}
+
+#[test]
+fn should_pipe_standard_input_to_standard_output() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", "-", "--source-format", "csv", "--transformer", "traderjoes", "--output", "-", "--output-format", "csv",
+            "--fail-on-discard"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"Order Number,Year,Month,Day,Product Number,Product Name,Count\n\
+                                            13,2019,8,27,123456789,Nuts,12\n").unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "Order Id,Date Time,Product Id,Product Name,Quantity,Unit,Unit Price,Currency,Total\n\
+                    13,2019-08-27,123456789,Nuts,12,EACH,,,\n");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("loaded: 1 (discarded: 0)"));
+}
+
+#[test]
+fn should_aggregate_line_items() {
+    let directory = tempdir().unwrap();
+    let source = write_source(directory.path(), "13,2019,8,27,123456789,Nuts,12\n\
+                                                 13,2019,8,27,987654321,Jam,1\n\
+                                                 16,2019,8,28,987654321,Jam,2\n");
+    let output = directory.path().join("orders.ndjson");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--transformer", "traderjoes", "--output", output.to_str().unwrap(),
+            "--aggregate", "consecutive"])
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(output).unwrap(), "\
+        {\"id\":13,\"date\":\"2019-08-27\",\"line_items\":[\
+            {\"product_id\":\"123456789\",\"product_name\":\"Nuts\",\"quantity\":12,\"unit\":\"EACH\"},\
+            {\"product_id\":\"987654321\",\"product_name\":\"Jam\",\"quantity\":1,\"unit\":\"EACH\"}]}\n\
+        {\"id\":16,\"date\":\"2019-08-28\",\"line_items\":[\
+            {\"product_id\":\"987654321\",\"product_name\":\"Jam\",\"quantity\":2,\"unit\":\"EACH\"}]}\n");
+    assert!(String::from_utf8(result.stderr).unwrap().contains("loaded: 2 (discarded: 0)"));
+}
+
+#[test]
+fn should_report_discarded_orders_with_lines_of_their_records() {
+    let directory = tempdir().unwrap();
+    let source = write_source(directory.path(), "13,2019,8,27,123456789,Nuts,12\n\
+                                                 13,2019,8,28,987654321,Jam,1\n");
+    let output = directory.path().join("orders.ndjson");
+    let rejects = directory.path().join("rejects.txt");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--transformer", "traderjoes", "--output", output.to_str().unwrap(),
+            "--aggregate", "keyed", "--rejects", rejects.to_str().unwrap()])
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(rejects).unwrap(), "order 13 (line 3): Order date differs from the one of its other line items.\n");
+    assert!(String::from_utf8(result.stderr).unwrap().contains("discarded orders:\n  Order date differs from the one of its other line items.: 1"));
+}
+
+#[test]
+fn should_discard_records_not_convertible_to_unit() {
+    let directory = tempdir().unwrap();
+    let source = write_source(directory.path(), "13,2019,8,27,123456789,Nuts,12\n");
+    let output = directory.path().join("orders.csv");
+    let rejects = directory.path().join("rejects.txt");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--transformer", "traderjoes", "--unit", "kg", "--output", output.to_str().unwrap(),
+            "--rejects", rejects.to_str().unwrap()])
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(output).unwrap(), "Order Id,Date Time,Product Id,Product Name,Quantity,Unit,Unit Price,Currency,Total\n");
+    assert_eq!(fs::read_to_string(rejects).unwrap(), "record 1: quantity: cannot convert EACH to KG\n");
+    assert!(String::from_utf8(result.stderr).unwrap().contains("transformed: 0 (discarded: 1)"));
+}
+
+#[test]
+fn should_fail_on_unknown_format() {
+    let directory = tempdir().unwrap();
+    let source = write_source(directory.path(), "");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_poor-man-etl"))
+        .args(["--source", source.to_str().unwrap(), "--transformer", "traderjoes", "--output", "orders.xml"])
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(1));
+    assert!(String::from_utf8(result.stderr).unwrap().contains("unknown output format of orders.xml"));
+}
+
+fn write_source(directory: &Path, rows: &str) -> std::path::PathBuf {
+    let source = directory.join("source.csv");
+    fs::write(&source, format!("Order Number,Year,Month,Day,Product Number,Product Name,Count\n{}", rows)).unwrap();
+    source
+}
@@ -4,6 +4,7 @@ use std::io::{Read, Seek, SeekFrom, Write};
 use poor_man_etl::csv::extractor::CsvExtractor;
 use poor_man_etl::csv::loader::CsvLoader;
 use poor_man_etl::engine::Engine;
+use poor_man_etl::extractor::ExtractorError;
 use poor_man_etl::loader::DiscardedOrder;
 use poor_man_etl::reporter::Reporter;
 use poor_man_etl::traderjoes::transformer::TraderJoesTransformer;
@@ -17,14 +18,16 @@ fn should_extract_from_traderjoes_csv_and_load_to_csv() {
     let reporter = PanickingReporter {};
     let (mut loader, mut target_file) = create_loader();
 
-    Engine::etl(&mut extractor, &transformer, &reporter, &mut loader);
+    let report = Engine::etl(&mut extractor, &transformer, &reporter, &mut loader).unwrap();
 
     let mut loaded_content = String::new();
     target_file.seek(SeekFrom::Start(0)).unwrap();
     target_file.read_to_string(&mut loaded_content).unwrap();
-    assert_eq!(loaded_content, "Order Id,Date Time,Product Id,Product Name,Quantity,Unit\n\
-                    13,2019-08-27,123456789,Nuts,12,KG\n\
-                    16,2019-08-28,987654321,Jam,1,KG\n");
+    assert_eq!(loaded_content, "Order Id,Date Time,Product Id,Product Name,Quantity,Unit,Unit Price,Currency,Total\n\
+                    13,2019-08-27,123456789,Nuts,12,EACH,,,\n\
+                    16,2019-08-28,987654321,Jam,1,EACH,,,\n");
+    assert_eq!(report.extracted(), 2);
+    assert_eq!(report.loaded(), 2);
 }
 
 fn create_extractor() -> CsvExtractor {
@@ -52,4 +55,8 @@ impl Reporter for PanickingReporter {
     fn report_order(&self, _discarded_order: DiscardedOrder) {
         panic!("Order discarded - should never happen!");
     }
+
+    fn report_extraction(&self, _extractor_error: ExtractorError) {
+        panic!("Extraction failed - should never happen!");
+    }
 }
\ No newline at end of file
@@ -1 +1,4 @@
-mod csv;
\ No newline at end of file
+mod cli;
+mod csv;
+mod json;
+mod sqlite;
\ No newline at end of file
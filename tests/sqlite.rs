@@ -0,0 +1,60 @@
+use std::path::Path;
+
+use poor_man_etl::engine::Engine;
+use poor_man_etl::extractor::ExtractorError;
+use poor_man_etl::loader::DiscardedOrder;
+use poor_man_etl::reporter::Reporter;
+use poor_man_etl::sqlite::extractor::{RecordId, SqlExtractor};
+use poor_man_etl::sqlite::loader::SqliteLoader;
+use poor_man_etl::traderjoes::transformer::TraderJoesTransformer;
+use poor_man_etl::transformer::DiscardedRecord;
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+#[test]
+fn should_extract_from_traderjoes_sqlite_and_load_to_sqlite() {
+    let directory = tempdir().unwrap();
+    let source_path = directory.path().join("source.db");
+    let target_path = directory.path().join("target.db");
+    let mut extractor = create_extractor(&source_path);
+    let transformer = TraderJoesTransformer::new();
+    let reporter = PanickingReporter {};
+    let mut loader = SqliteLoader::to(Connection::open(&target_path).unwrap()).unwrap();
+
+    Engine::etl(&mut extractor, &transformer, &reporter, &mut loader).unwrap();
+
+    let connection = Connection::open(&target_path).unwrap();
+    let mut statement = connection.prepare("SELECT id, date, product_id, product_name, quantity, unit \
+                                                  FROM orders JOIN order_lines ON order_id = id ORDER BY id, line").unwrap();
+    let loaded: Vec<String> = statement.query_map([], |row| Ok(format!("{},{},{},{},{},{}",
+                                                                         row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?,
+                                                                         row.get::<_, String>(3)?, row.get::<_, String>(4)?, row.get::<_, String>(5)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(loaded, vec!["13,2019-08-27,123456789,Nuts,12,EACH", "16,2019-08-28,987654321,Jam,1,EACH"]);
+}
+
+fn create_extractor(path: &Path) -> SqlExtractor {
+    let connection = Connection::open(path).unwrap();
+    connection.execute_batch("CREATE TABLE sales (\"Order Number\" INTEGER, Year INTEGER, Month INTEGER, Day INTEGER, \"Product Number\" TEXT, \"Product Name\" TEXT, Count INTEGER);\n\
+                         INSERT INTO sales VALUES (13, 2019, 8, 27, '123456789', 'Nuts', 12);\n\
+                         INSERT INTO sales VALUES (16, 2019, 8, 28, '987654321', 'Jam', 1);").unwrap();
+    SqlExtractor::query(connection, "SELECT * FROM sales", RecordId::RowNumber).unwrap()
+}
+
+struct PanickingReporter {}
+
+impl Reporter for PanickingReporter {
+    fn report_record(&self, _discarded_record: DiscardedRecord) {
+        panic!("Record discarded - should never happen!");
+    }
+
+    fn report_order(&self, _discarded_order: DiscardedOrder) {
+        panic!("Order discarded - should never happen!");
+    }
+
+    fn report_extraction(&self, _extractor_error: ExtractorError) {
+        panic!("Extraction failed - should never happen!");
+    }
+}